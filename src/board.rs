@@ -0,0 +1,456 @@
+//! Animation frames of a drawing board.
+//!
+//! Every frame has the same layers (names,visibility,opacity...) with its own pixels,
+//! so a layer change is applied to all the frames at once.
+use serde::{Deserialize, Serialize};
+use shared::DrawingPixelCanvas;
+
+use crate::layers::{LayerOp, LayerStack};
+
+/// A board can't have more frames than this
+pub const MAX_FRAMES: usize = 256;
+/// Duration of a new frame
+pub const DEFAULT_FRAME_DURATION_MS: u32 = 100;
+pub const MIN_FRAME_DURATION_MS: u32 = 10;
+pub const MAX_FRAME_DURATION_MS: u32 = 10_000;
+/// Longest side of a board
+pub const MAX_BOARD_SIDE: usize = 500;
+/// Most cells of a board over all its frames and layers,every one is a pixel held in memory
+pub const MAX_BOARD_CELLS: usize = 1 << 24;
+
+/// Check a board size before anything is allocated for it,every side must be between 1 and [`MAX_BOARD_SIDE`]
+pub fn check_dimension(width: usize, height: usize) -> Result<(), String> {
+    if !(1..=MAX_BOARD_SIDE).contains(&width) || !(1..=MAX_BOARD_SIDE).contains(&height) {
+        return Err(format!(
+            "a board must be between 1x1 and {MAX_BOARD_SIDE}x{MAX_BOARD_SIDE},not {width}x{height}"
+        ));
+    }
+    Ok(())
+}
+
+/// Check the cells held by a board of the given shape against [`MAX_BOARD_CELLS`]
+fn check_cells(
+    frames: usize,
+    layers: usize,
+    (width, height): (usize, usize),
+) -> Result<(), String> {
+    let cells = frames
+        .saturating_mul(layers)
+        .saturating_mul(width)
+        .saturating_mul(height);
+    if cells > MAX_BOARD_CELLS {
+        return Err(format!(
+            "{frames} frames of {layers} {width}x{height} layers are more than the {MAX_BOARD_CELLS} cells of a board"
+        ));
+    }
+    Ok(())
+}
+
+/// Side or corner of a board that stays in place when it is resized
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Anchor {
+    #[default]
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    /// Row by row,like they are laid out on the board
+    pub const ALL: [Anchor; 9] = [
+        Anchor::TopLeft,
+        Anchor::Top,
+        Anchor::TopRight,
+        Anchor::Left,
+        Anchor::Center,
+        Anchor::Right,
+        Anchor::BottomLeft,
+        Anchor::Bottom,
+        Anchor::BottomRight,
+    ];
+    pub fn name(&self) -> &'static str {
+        match self {
+            Anchor::TopLeft => "Top left",
+            Anchor::Top => "Top",
+            Anchor::TopRight => "Top right",
+            Anchor::Left => "Left",
+            Anchor::Center => "Center",
+            Anchor::Right => "Right",
+            Anchor::BottomLeft => "Bottom left",
+            Anchor::Bottom => "Bottom",
+            Anchor::BottomRight => "Bottom right",
+        }
+    }
+    pub fn arrow(&self) -> &'static str {
+        match self {
+            Anchor::TopLeft => "↖",
+            Anchor::Top => "↑",
+            Anchor::TopRight => "↗",
+            Anchor::Left => "←",
+            Anchor::Center => "•",
+            Anchor::Right => "→",
+            Anchor::BottomLeft => "↙",
+            Anchor::Bottom => "↓",
+            Anchor::BottomRight => "↘",
+        }
+    }
+    /// Where the top left pixel of a board of size `from` lands once resized to `to`,
+    /// negative when pixels are cut off
+    pub fn offset(&self, from: (usize, usize), to: (usize, usize)) -> (isize, isize) {
+        let grow_x = to.0 as isize - from.0 as isize;
+        let grow_y = to.1 as isize - from.1 as isize;
+        let x = match self {
+            Anchor::TopLeft | Anchor::Left | Anchor::BottomLeft => 0,
+            Anchor::Top | Anchor::Center | Anchor::Bottom => grow_x / 2,
+            Anchor::TopRight | Anchor::Right | Anchor::BottomRight => grow_x,
+        };
+        let y = match self {
+            Anchor::TopLeft | Anchor::Top | Anchor::TopRight => 0,
+            Anchor::Left | Anchor::Center | Anchor::Right => grow_y / 2,
+            Anchor::BottomLeft | Anchor::Bottom | Anchor::BottomRight => grow_y,
+        };
+        (x, y)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Frame {
+    /// Stable id,the index of a frame changes when it is moved
+    pub id: u32,
+    /// How long the frame is shown during playback
+    pub duration_ms: u32,
+    pub layers: LayerStack,
+}
+
+/// Change of the frame list,applied the same way by the server and every client
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum FrameOp {
+    /// New frame at `index`,a copy of `copy_of` or empty. The id is given by the server
+    Add {
+        id: u32,
+        index: usize,
+        copy_of: Option<u32>,
+    },
+    Remove { id: u32 },
+    /// Move a frame to `index`,0 is the first frame
+    Move { id: u32, index: usize },
+    SetDuration { id: u32, duration_ms: u32 },
+}
+
+/// Frames of a board,in playback order.
+/// There is always at least one frame.
+#[derive(Clone, Debug)]
+pub struct Board {
+    frames: Vec<Frame>,
+    /// Id of the next added frame,ids are never reused
+    next_id: u32,
+}
+
+impl Board {
+    /// Board with a single frame and an empty background layer
+    pub fn new(width: usize, height: usize) -> Self {
+        Self::from_layers(LayerStack::new(width, height))
+    }
+    /// Board with a single frame
+    pub fn from_layers(layers: LayerStack) -> Self {
+        Self {
+            frames: vec![Frame {
+                id: 0,
+                duration_ms: DEFAULT_FRAME_DURATION_MS,
+                layers,
+            }],
+            next_id: 1,
+        }
+    }
+    /// Rebuild a board,the frames must all have the same dimension
+    pub fn from_frames(frames: Vec<Frame>, next_id: u32) -> Result<Self, String> {
+        let first = frames.first().ok_or("a board needs at least one frame")?;
+        let dimension = first.layers.dimension();
+        if frames.iter().any(|frame| frame.layers.dimension() != dimension) {
+            return Err("the frames don't have the same dimension".to_string());
+        }
+        let next_id = frames
+            .iter()
+            .map(|frame| frame.id + 1)
+            .max()
+            .unwrap_or(0)
+            .max(next_id);
+        Ok(Self { frames, next_id })
+    }
+
+    pub fn dimension(&self) -> (usize, usize) {
+        self.first().layers.dimension()
+    }
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+    pub fn next_id(&self) -> u32 {
+        self.next_id
+    }
+    pub fn first(&self) -> &Frame {
+        &self.frames[0]
+    }
+    pub fn first_mut(&mut self) -> &mut Frame {
+        &mut self.frames[0]
+    }
+    pub fn get(&self, id: u32) -> Option<&Frame> {
+        self.frames.iter().find(|frame| frame.id == id)
+    }
+    pub fn get_mut(&mut self, id: u32) -> Option<&mut Frame> {
+        self.frames.iter_mut().find(|frame| frame.id == id)
+    }
+    /// Layers of every frame,the frames all have the same ones
+    pub fn layer_count(&self) -> usize {
+        self.first().layers.layers().len()
+    }
+    pub fn index_of(&self, id: u32) -> Option<usize> {
+        self.frames.iter().position(|frame| frame.id == id)
+    }
+    /// Pixels of a layer in a frame
+    pub fn canvas_mut(&mut self, frame: u32, layer: u32) -> Option<&mut DrawingPixelCanvas> {
+        Some(&mut self.get_mut(frame)?.layers.get_mut(layer)?.canvas)
+    }
+
+    /// Give an id to a new layer or frame before the op is applied and shared
+    pub fn assign_layer_id(&self, op: &mut LayerOp) {
+        self.first().layers.assign_id(op);
+    }
+    pub fn assign_frame_id(&self, op: &mut FrameOp) {
+        if let FrameOp::Add { id, .. } = op {
+            *id = self.next_id;
+        }
+    }
+
+    /// Apply a layer change to every frame,or to none of them if a frame rejects it
+    pub fn apply_layer_op(&mut self, op: &LayerOp) -> Result<(), String> {
+        if let LayerOp::Add { .. } = op {
+            check_cells(self.frames.len(), self.layer_count() + 1, self.dimension())?;
+        }
+        for frame in &self.frames {
+            frame.layers.check(op)?;
+        }
+        for frame in &mut self.frames {
+            frame.layers.apply(op)?;
+        }
+        Ok(())
+    }
+
+    pub fn apply_frame_op(&mut self, op: &FrameOp) -> Result<(), String> {
+        match op {
+            FrameOp::Add { id, index, copy_of } => {
+                if self.frames.len() >= MAX_FRAMES {
+                    return Err(format!("at most {MAX_FRAMES} frames are supported"));
+                }
+                if self.get(*id).is_some() {
+                    return Err(format!("frame {id} already exists"));
+                }
+                check_cells(self.frames.len() + 1, self.layer_count(), self.dimension())?;
+                let frame = match copy_of {
+                    Some(copy_of) => {
+                        let source = self.get(*copy_of).ok_or(format!("no frame {copy_of}"))?;
+                        Frame {
+                            id: *id,
+                            ..source.clone()
+                        }
+                    }
+                    None => Frame {
+                        id: *id,
+                        duration_ms: DEFAULT_FRAME_DURATION_MS,
+                        layers: self.first().layers.empty_copy(),
+                    },
+                };
+                let index = (*index).min(self.frames.len());
+                self.frames.insert(index, frame);
+                self.next_id = self.next_id.max(id + 1);
+            }
+            FrameOp::Remove { id } => {
+                if self.frames.len() == 1 {
+                    return Err("the last frame can't be removed".to_string());
+                }
+                let index = self.index_of(*id).ok_or(format!("no frame {id}"))?;
+                self.frames.remove(index);
+            }
+            FrameOp::Move { id, index } => {
+                let from = self.index_of(*id).ok_or(format!("no frame {id}"))?;
+                let frame = self.frames.remove(from);
+                let index = (*index).min(self.frames.len());
+                self.frames.insert(index, frame);
+            }
+            FrameOp::SetDuration { id, duration_ms } => {
+                let frame = self.get_mut(*id).ok_or(format!("no frame {id}"))?;
+                frame.duration_ms = (*duration_ms).clamp(MIN_FRAME_DURATION_MS, MAX_FRAME_DURATION_MS);
+            }
+        }
+        Ok(())
+    }
+    /// Erase the pixels of every layer of every frame,the layers are kept
+    pub fn clear(&mut self) {
+        for frame in &mut self.frames {
+            frame.layers.clear();
+        }
+    }
+
+    /// Change the size of every frame,the pixels stay at the anchor and the ones outside are lost
+    pub fn resize(&mut self, width: usize, height: usize, anchor: Anchor) -> Result<(), String> {
+        let offset = anchor.offset(self.dimension(), (width, height));
+        self.reframe(width, height, offset)
+    }
+
+    /// Keep only the given rectangle of every frame
+    pub fn crop(&mut self, x: usize, y: usize, width: usize, height: usize) -> Result<(), String> {
+        let (board_width, board_height) = self.dimension();
+        if x + width > board_width || y + height > board_height {
+            return Err(format!("{width}x{height} at ({x},{y}) is outside the board"));
+        }
+        self.reframe(width, height, (-(x as isize), -(y as isize)))
+    }
+
+    /// Give every frame the new size,moving the pixels by `offset`
+    fn reframe(&mut self, width: usize, height: usize, offset: (isize, isize)) -> Result<(), String> {
+        check_dimension(width, height)?;
+        check_cells(self.frames.len(), self.layer_count(), (width, height))?;
+        for frame in &mut self.frames {
+            frame.layers.resize(width, height, offset);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::MAX_LAYERS;
+    use shared::PixelColor;
+
+    /// Pixel of the bottom layer of a frame
+    fn pixel(board: &Board, frame: usize, x: usize, y: usize) -> PixelColor {
+        board.frames()[frame].layers.layers()[0]
+            .canvas
+            .get_pixel(x, y)
+    }
+
+    #[test]
+    fn checks_the_board_size() {
+        assert!(check_dimension(1, 1).is_ok());
+        assert!(check_dimension(MAX_BOARD_SIDE, MAX_BOARD_SIDE).is_ok());
+        assert!(check_dimension(0, 1).is_err());
+        assert!(check_dimension(1, 0).is_err());
+        assert!(check_dimension(MAX_BOARD_SIDE + 1, 1).is_err());
+    }
+
+    #[test]
+    fn anchor_offset_when_growing() {
+        let (from, to) = ((4, 4), (6, 8));
+        assert_eq!(Anchor::TopLeft.offset(from, to), (0, 0));
+        assert_eq!(Anchor::Center.offset(from, to), (1, 2));
+        assert_eq!(Anchor::Right.offset(from, to), (2, 2));
+        assert_eq!(Anchor::Bottom.offset(from, to), (1, 4));
+        assert_eq!(Anchor::BottomRight.offset(from, to), (2, 4));
+    }
+
+    #[test]
+    fn anchor_offset_when_shrinking() {
+        let (from, to) = ((6, 6), (2, 4));
+        assert_eq!(Anchor::TopLeft.offset(from, to), (0, 0));
+        assert_eq!(Anchor::Center.offset(from, to), (-2, -1));
+        assert_eq!(Anchor::BottomRight.offset(from, to), (-4, -2));
+    }
+
+    #[test]
+    fn anchor_offset_of_odd_changes_leans_to_the_top_left() {
+        assert_eq!(Anchor::Center.offset((4, 4), (7, 5)), (1, 0));
+        assert_eq!(Anchor::Center.offset((5, 5), (2, 2)), (-1, -1));
+    }
+
+    #[test]
+    fn resize_keeps_the_pixels_at_the_anchor() {
+        let mut board = Board::new(2, 2);
+        board
+            .canvas_mut(0, 0)
+            .unwrap()
+            .draw_pixel_ignore(0, 0, PixelColor::RED);
+        board.resize(4, 4, Anchor::Center).unwrap();
+        assert_eq!(board.dimension(), (4, 4));
+        assert_eq!(pixel(&board, 0, 1, 1), PixelColor::RED);
+        assert_eq!(pixel(&board, 0, 0, 0), PixelColor::TRANSPARENT);
+
+        let mut board = Board::new(3, 3);
+        let canvas = board.canvas_mut(0, 0).unwrap();
+        canvas.draw_pixel_ignore(0, 0, PixelColor::RED);
+        canvas.draw_pixel_ignore(2, 2, PixelColor::GREEN);
+        let mut top_left = board.clone();
+        top_left.resize(1, 1, Anchor::TopLeft).unwrap();
+        assert_eq!(pixel(&top_left, 0, 0, 0), PixelColor::RED);
+        board.resize(1, 1, Anchor::BottomRight).unwrap();
+        assert_eq!(pixel(&board, 0, 0, 0), PixelColor::GREEN);
+    }
+
+    #[test]
+    fn resize_rejects_invalid_sizes() {
+        let mut board = Board::new(2, 2);
+        assert!(board.resize(0, 2, Anchor::Center).is_err());
+        assert!(board.resize(2, MAX_BOARD_SIDE + 1, Anchor::Center).is_err());
+        assert_eq!(board.dimension(), (2, 2));
+    }
+
+    #[test]
+    fn limits_the_cells_of_every_frame_and_layer() {
+        let side = (MAX_BOARD_SIDE, MAX_BOARD_SIDE);
+        assert!(check_cells(1, 1, side).is_ok());
+        assert!(check_cells(MAX_FRAMES, MAX_LAYERS, side).is_err());
+
+        let mut board = Board::new(100, 100);
+        let frames = MAX_BOARD_CELLS / (MAX_BOARD_SIDE * MAX_BOARD_SIDE) + 1;
+        for _ in 1..frames {
+            let mut add_frame = FrameOp::Add {
+                id: 0,
+                index: 0,
+                copy_of: None,
+            };
+            board.assign_frame_id(&mut add_frame);
+            board.apply_frame_op(&add_frame).unwrap();
+        }
+        let (width, height) = side;
+        assert!(board.resize(width, height, Anchor::Center).is_err());
+        assert_eq!(board.dimension(), (100, 100));
+    }
+
+    #[test]
+    fn crop_keeps_the_rectangle_of_every_frame() {
+        let mut board = Board::new(4, 3);
+        board
+            .canvas_mut(0, 0)
+            .unwrap()
+            .draw_pixel_ignore(2, 1, PixelColor::RED);
+        let mut add_frame = FrameOp::Add {
+            id: 0,
+            index: 1,
+            copy_of: Some(0),
+        };
+        board.assign_frame_id(&mut add_frame);
+        board.apply_frame_op(&add_frame).unwrap();
+
+        board.crop(1, 1, 2, 2).unwrap();
+        assert_eq!(board.dimension(), (2, 2));
+        for frame in 0..2 {
+            assert_eq!(board.frames()[frame].layers.dimension(), (2, 2));
+            assert_eq!(pixel(&board, frame, 1, 0), PixelColor::RED);
+            assert_eq!(pixel(&board, frame, 0, 0), PixelColor::TRANSPARENT);
+        }
+    }
+
+    #[test]
+    fn crop_rejects_rectangles_outside_the_board() {
+        let mut board = Board::new(4, 3);
+        assert!(board.crop(3, 0, 2, 1).is_err());
+        assert!(board.crop(0, 2, 1, 2).is_err());
+        assert!(board.crop(0, 0, 0, 1).is_err());
+        assert_eq!(board.dimension(), (4, 3));
+    }
+}
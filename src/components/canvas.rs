@@ -1,13 +1,20 @@
+use std::time::Duration;
+
 use frontend::prelude::PixelColor;
 use leptos::html;
-use leptos::logging::log;
+use leptos::logging::{error, log};
 use leptos::prelude::*;
+use leptos::task::spawn_local;
 use leptos::{ev, leptos_dom::helpers::window_event_listener};
 use wasm_bindgen::JsCast;
 use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement};
 
 use crate::components::DrawingTool;
-use crate::types::pixel_canvas::{CANVAS_BACKGROUND_COLOR, GridIndex, PixelCanvas};
+use crate::services::clipboard;
+use crate::types::color::RecentColors;
+use crate::types::pixel_canvas::{CANVAS_BACKGROUND_COLOR, GridIndex, PixelCanvas, SELECTION_DASH};
+use crate::types::presence::Participants;
+use crate::types::selection::PixelRegion;
 
 pub static mut PEN_TOUCHED: bool = false;
 pub static mut GREEN_TOUCHED: bool = false;
@@ -33,6 +40,13 @@ fn get_canvas_2d_context(canvas: &HtmlCanvasElement) -> Result<CanvasRenderingCo
 pub fn Canvas(
     #[prop(into)] canvas_state: RwSignal<PixelCanvas>,
     #[prop(into)] selected_tool: RwSignal<DrawingTool>,
+    recent_colors: RwSignal<RecentColors>,
+    /// Cell under the mouse,None when the mouse is off the grid
+    #[prop(into)] hovered_cell: RwSignal<Option<GridIndex>>,
+    /// The other users,their cursors are drawn over the grid
+    #[prop(into)] participants: Signal<Participants>,
+    /// View mode: the board can be moved and zoomed but not changed
+    #[prop(into)] read_only: Signal<bool>,
 ) -> impl IntoView {
     let canvas_ref = NodeRef::<html::Canvas>::new();
     // Create RwSignal for pixel canvas state
@@ -40,19 +54,134 @@ pub fn Canvas(
     #[derive(Clone, Debug)]
     enum DrawingState {
         NotClicked,
-        Clicked { last_position: GridIndex },
+        Clicked {
+            /// Where the mouse was pressed,the anchor of shapes
+            start_position: GridIndex,
+            last_position: GridIndex,
+        },
     }
 
     // Mouse interaction state
     let is_dragging = RwSignal::new(false);
     let mouse_position = RwSignal::new(None::<(f64, f64)>);
     let drawing_state = RwSignal::new(DrawingState::NotClicked);
+    // True while the selection is dragged,false while it is being drawn
+    let moving_selection = RwSignal::new(false);
+    // Offset of the selection marching ants
+    let ants_offset = RwSignal::new(0.0);
+
+    //region eyedropper
+    // Tool the eyedropper gives its color to,the last tool picked before it
+    let previous_tool = StoredValue::new(DrawingTool::default());
+    Effect::new(move |_| {
+        let tool = selected_tool.get();
+        if tool != DrawingTool::Eyedropper {
+            previous_tool.set_value(tool);
+        }
+    });
+    // Give the color under the mouse to the current tool,or to the pen if the tool has no color
+    let pick_color = move |mouse_x: f64, mouse_y: f64| {
+        let color = canvas_state.with_untracked(|pc| {
+            let pos = crate::prelude::Position::new(mouse_x, mouse_y);
+            pc.sample_color(&pc.closest_grid_index_from_point(pos))
+        });
+        let Some(color) = color else {
+            return;
+        };
+        recent_colors.update(|recent| recent.push(color));
+        selected_tool.update(|tool| {
+            if *tool == DrawingTool::Eyedropper {
+                *tool = previous_tool.get_value();
+            }
+            match tool.color() {
+                Some(_) => tool.change_color(color),
+                // The eraser and the selection hand the picked color to the pen,
+                // with the eyedropper as with Alt+click,so it is never lost
+                None => *tool = DrawingTool::Pen(color),
+            }
+        });
+    };
+    //endregion
+
+    //region selection
+    // Changing tool drops the selection,so the floating pixels aren't lost
+    Effect::new(move |_| {
+        if selected_tool.get() != DrawingTool::Select
+            && canvas_state.with_untracked(|pc| pc.selection().is_some())
+        {
+            canvas_state.update(|pc| pc.clear_selection());
+        }
+    });
+    set_interval(
+        move || {
+            if canvas_state.with_untracked(|pc| pc.selection().is_some()) {
+                ants_offset.update(|offset| *offset = (*offset + 1.0) % (SELECTION_DASH * 2.0));
+            }
+        },
+        Duration::from_millis(100),
+    );
+    // Grid cell under the mouse,where pasted regions go
+    let paste_position = move || {
+        let mouse = mouse_position.get_untracked();
+        canvas_state.with_untracked(|pc| {
+            mouse
+                .map(|(x, y)| pc.closest_grid_index_from_point(crate::prelude::Position::new(x, y)))
+                .unwrap_or(GridIndex { x: 0, y: 0 })
+        })
+    };
+    let copy_to_clipboard = move |region: PixelRegion| {
+        spawn_local(async move {
+            let result = match region.to_png_bytes() {
+                Ok(bytes) => clipboard::write_png(&bytes).await,
+                Err(err) => Err(err),
+            };
+            if let Err(err) = result {
+                // The internal clipboard still has the region
+                log!("Couldn't copy to the system clipboard: {err}");
+            }
+        });
+    };
+    let paste_from_clipboard = move || {
+        let at = paste_position();
+        let (width, height) = canvas_state.with_untracked(|pc| pc.grid_dimension());
+        selected_tool.set(DrawingTool::Select);
+        spawn_local(async move {
+            let region = match clipboard::read_png().await {
+                Ok(Some(bytes)) => PixelRegion::from_image_bytes(&bytes, width, height)
+                    .inspect_err(|err| error!("{err}"))
+                    .ok(),
+                Ok(None) => None,
+                Err(err) => {
+                    log!("Couldn't read the system clipboard: {err}");
+                    None
+                }
+            };
+            canvas_state.update(|pc| match region {
+                Some(region) => pc.paste(region, &at),
+                None => {
+                    pc.paste_clipboard(&at);
+                }
+            });
+        });
+    };
+    //endregion
 
     //region input handler
     //region handle keyboard
     let handle_keydown = move |ev: web_sys::KeyboardEvent| {
         let step = 20.0; // Movement step size
-        match ev.key().as_str() {
+        let key = ev.key();
+        let navigation = matches!(
+            key.as_str(),
+            "ArrowLeft" | "ArrowRight" | "ArrowUp" | "ArrowDown" | "=" | "+" | "-"
+        );
+        if read_only.get_untracked() && !navigation {
+            return;
+        }
+        let plain_shift = ev.shift_key() && !ev.ctrl_key() && !ev.meta_key() && !ev.alt_key();
+        // Delete,Enter and Escape keep their usual meaning while nothing is selected
+        let selected = canvas_state.with_untracked(|pc| pc.selection().is_some());
+        match key.as_str() {
             "ArrowLeft" => {
                 canvas_state.update(|pc| pc.x_shift(step));
                 ev.prevent_default();
@@ -79,29 +208,105 @@ pub fn Canvas(
                 canvas_state.update(|pc| pc.zoom_out(1.2));
                 ev.prevent_default();
             }
+            "z" | "Z" if ev.ctrl_key() || ev.meta_key() => {
+                // Ctrl+Z undo,Ctrl+Shift+Z redo
+                if ev.shift_key() {
+                    canvas_state.update(|pc| pc.redo());
+                } else {
+                    canvas_state.update(|pc| pc.undo());
+                }
+                ev.prevent_default();
+            }
+            "y" if ev.ctrl_key() || ev.meta_key() => {
+                canvas_state.update(|pc| pc.redo());
+                ev.prevent_default();
+            }
+            "c" | "C" if ev.ctrl_key() || ev.meta_key() => {
+                if let Some(region) = canvas_state.try_update(|pc| pc.copy_selection()).flatten() {
+                    copy_to_clipboard(region);
+                }
+                ev.prevent_default();
+            }
+            "x" | "X" if ev.ctrl_key() || ev.meta_key() => {
+                if let Some(region) = canvas_state.try_update(|pc| pc.cut_selection()).flatten() {
+                    copy_to_clipboard(region);
+                }
+                ev.prevent_default();
+            }
+            "v" | "V" if ev.ctrl_key() || ev.meta_key() => {
+                paste_from_clipboard();
+                ev.prevent_default();
+            }
+            "a" | "A" if ev.ctrl_key() || ev.meta_key() => {
+                selected_tool.set(DrawingTool::Select);
+                canvas_state.update(|pc| pc.select_all());
+                ev.prevent_default();
+            }
+            "Delete" | "Backspace" if selected => {
+                canvas_state.update(|pc| pc.delete_selection());
+                ev.prevent_default();
+            }
+            "Enter" if selected => {
+                // Drop the moved or pasted pixels
+                canvas_state.update(|pc| pc.clear_selection());
+                ev.prevent_default();
+            }
+            "Escape" if selected => {
+                canvas_state.update(|pc| pc.cancel_selection());
+                ev.prevent_default();
+            }
+            "i" | "I" if !ev.ctrl_key() && !ev.meta_key() => {
+                selected_tool.set(DrawingTool::Eyedropper);
+                ev.prevent_default();
+            }
+            // Shift+U undo,by physical key so caps lock and other layouts don't trigger it.
+            // Ctrl+Shift+R is left to the browser
+            _ if plain_shift && ev.code() == "KeyU" => {
+                canvas_state.update(|pc| pc.undo());
+                ev.prevent_default();
+            }
+            // Shift+R redo
+            _ if plain_shift && ev.code() == "KeyR" => {
+                canvas_state.update(|pc| pc.redo());
+                ev.prevent_default();
+            }
             _ => {}
         }
     };
     //endregion
     //region handle mouse
     let handle_mousedown = move |ev: web_sys::MouseEvent| {
+        // The default action is prevented below,so take the focus back from the panels' inputs
+        // for the keyboard shortcuts
+        if let Some(canvas) = canvas_ref.get_untracked() {
+            let _ = canvas.focus();
+        }
         match ev.button() {
             1 => {
                 // Middle mouse button - panning
                 is_dragging.set(true);
                 ev.prevent_default();
             }
+            0 if read_only.get_untracked() => ev.prevent_default(),
             0 => {
                 // Left mouse button - drawing/erasing
                 let mouse_x = ev.client_x() as f64;
                 let mouse_y = ev.client_y() as f64;
+                // Alt+click picks a color with any tool
+                if ev.alt_key() || selected_tool.get_untracked() == DrawingTool::Eyedropper {
+                    pick_color(mouse_x, mouse_y);
+                    ev.prevent_default();
+                    return;
+                }
                 //region save drawing position and tool update
                 canvas_state.update(|pc| {
                     let pos = crate::prelude::Position::new(mouse_x, mouse_y);
                     let grid_pos = pc.closest_grid_index_from_point(pos);
                     drawing_state.set(DrawingState::Clicked {
+                        start_position: grid_pos.clone(),
                         last_position: grid_pos,
                     });
+                    pc.begin_stroke();
 
                     let pos = crate::prelude::Position::new(mouse_x, mouse_y);
                     let current_pos = pc.closest_grid_index_from_point(pos);
@@ -124,6 +329,26 @@ pub fn Canvas(
                             log!("line drawn");
                             pc.pixel_draw(current_pos, color);
                         }
+                        tool @ (DrawingTool::Line(_)
+                        | DrawingTool::Rectangle(_)
+                        | DrawingTool::FilledRectangle(_)
+                        | DrawingTool::Ellipse(_)) => {
+                            if let Some((shape, color)) = tool.shape() {
+                                pc.preview_shape(shape, &current_pos, &current_pos, color);
+                            }
+                        }
+                        DrawingTool::Select => {
+                            if pc.selection_contains(&current_pos) {
+                                // Drag the selection,Ctrl duplicates it instead of moving
+                                pc.lift_selection(ev.ctrl_key());
+                                moving_selection.set(true);
+                            } else {
+                                pc.select(&current_pos, &current_pos);
+                                moving_selection.set(false);
+                            }
+                        }
+                        // Picked above,nothing is drawn
+                        DrawingTool::Eyedropper => {}
                     }
                 });
                 //endregion
@@ -140,9 +365,20 @@ pub fn Canvas(
                 is_dragging.set(false);
                 ev.prevent_default();
             }
+            0 if read_only.get_untracked() => {}
             0 => {
                 // Left mouse button - stop drawing
                 drawing_state.set(DrawingState::NotClicked);
+                canvas_state.update(|pc| {
+                    if selected_tool.get_untracked() == DrawingTool::Select {
+                        // Drop the dragged pixels,the selection stays to be moved again
+                        pc.commit_selection();
+                    } else {
+                        // Shapes are only previewed while dragging,draw them now
+                        pc.commit_preview();
+                    }
+                    pc.end_stroke();
+                });
                 ev.prevent_default();
             }
             _ => {} // Ignore other mouse buttons
@@ -154,6 +390,14 @@ pub fn Canvas(
         let mouse_x = ev.client_x() as f64;
         let mouse_y = ev.client_y() as f64;
         mouse_position.set(Some((mouse_x, mouse_y)));
+        let cell = canvas_state.with_untracked(|pc| {
+            let cell = pc.closest_grid_index_from_point(crate::prelude::Position::new(mouse_x, mouse_y));
+            let (width, height) = pc.grid_dimension();
+            (cell.x < width && cell.y < height).then_some(cell)
+        });
+        if hovered_cell.get_untracked() != cell {
+            hovered_cell.set(cell);
+        }
 
         // Handle panning (middle mouse drag)
         if is_dragging.get() {
@@ -169,7 +413,11 @@ pub fn Canvas(
         }
 
         // Handle drawing during mouse movement
-        if let DrawingState::Clicked { last_position } = drawing_state.get() {
+        if let DrawingState::Clicked {
+            start_position,
+            last_position,
+        } = drawing_state.get()
+        {
             let current_tool = selected_tool.get();
 
             canvas_state.update(|pc| {
@@ -188,7 +436,6 @@ pub fn Canvas(
                                 GREEN_TOUCHED = true;
                             }
                         }
-                        assert!(!pc.is_drawing_transperent());
                     }
                     DrawingTool::Eraser => {
                         // Erase line from last position to current position
@@ -199,7 +446,25 @@ pub fn Canvas(
                         }
                         pc.line_draw(last_position, current_pos, PixelColor::ERASE);
                     }
-                    DrawingTool::BucketFill(_) => {}
+                    DrawingTool::BucketFill(_) | DrawingTool::Eyedropper => {}
+                    tool @ (DrawingTool::Line(_)
+                    | DrawingTool::Rectangle(_)
+                    | DrawingTool::FilledRectangle(_)
+                    | DrawingTool::Ellipse(_)) => {
+                        // Rubber-band from the anchor to the mouse
+                        if let Some((shape, color)) = tool.shape() {
+                            pc.preview_shape(shape, &start_position, &current_pos, color);
+                        }
+                    }
+                    DrawingTool::Select => {
+                        if moving_selection.get_untracked() {
+                            let dx = current_pos.x as isize - last_position.x as isize;
+                            let dy = current_pos.y as isize - last_position.y as isize;
+                            pc.move_selection(dx, dy);
+                        } else {
+                            pc.select(&start_position, &current_pos);
+                        }
+                    }
                 }
             });
 
@@ -208,6 +473,7 @@ pub fn Canvas(
                 let pos = crate::prelude::Position::new(mouse_x, mouse_y);
                 let current_pos = pc.closest_grid_index_from_point(pos);
                 drawing_state.set(DrawingState::Clicked {
+                    start_position: start_position.clone(),
                     last_position: current_pos,
                 });
             });
@@ -219,6 +485,7 @@ pub fn Canvas(
     let handle_mouseleave = move |_ev: web_sys::MouseEvent| {
         // Clear mouse position when mouse leaves canvas
         mouse_position.set(None);
+        hovered_cell.set(None);
     };
 
     let handle_wheel = move |ev: web_sys::WheelEvent| {
@@ -272,13 +539,16 @@ pub fn Canvas(
 
         // Draw the pixel canvas using its draw method
         let canvas_state = canvas_state.get(); // This creates the reactive dependency
-        canvas_state.draw(&context, mouse_pos);
+        canvas_state.draw(&context, mouse_pos, ants_offset.get());
+        participants.with(|participants| canvas_state.draw_cursors(&context, participants.cursors()));
     };
     Effect::new(move |_| {
         // Create reactive dependencies
         let _canvas_state = canvas_state.get();
         let _mouse_pos = mouse_position.get();
         let _drawing_state = drawing_state.get(); // Add drawing state as dependency
+        let _ants_offset = ants_offset.get();
+        participants.track();
 
         if let Some(canvas) = canvas_ref.get() {
             draw(canvas)
@@ -0,0 +1,99 @@
+use frontend::protocol::{ChatMessage, MAX_CHAT_LEN, clean_chat};
+use leptos::html;
+use leptos::prelude::*;
+use wasm_bindgen::JsValue;
+use web_sys::js_sys;
+
+/// Local `HH:MM` of a message
+fn time_label(sent_at: u64) -> String {
+    let date = js_sys::Date::new(&JsValue::from_f64(sent_at as f64));
+    format!("{:02}:{:02}", date.get_hours(), date.get_minutes())
+}
+
+/// Chat of the session,collapsed to its header until opened.
+/// Keys typed in it never reach the canvas shortcuts.
+#[component]
+pub fn ChatPanel(
+    #[prop(into)] messages: Signal<Vec<ChatMessage>>,
+    #[prop(into)] on_send: Callback<String>,
+    /// False while the session isn't joined
+    #[prop(into)] can_send: Signal<bool>,
+) -> impl IntoView {
+    let open = RwSignal::new(false);
+    let draft = RwSignal::new(String::new());
+    // Messages that arrived while the panel was collapsed
+    let seen = RwSignal::new(0usize);
+    let unread = move || messages.with(|messages| messages.len()).saturating_sub(seen.get());
+    let list_ref = NodeRef::<html::Div>::new();
+
+    // Follow the new messages while the panel is open
+    Effect::new(move |_| {
+        let count = messages.with(|messages| messages.len());
+        if !open.get() {
+            return;
+        }
+        seen.set(count);
+        if let Some(list) = list_ref.get() {
+            list.set_scroll_top(list.scroll_height());
+        }
+    });
+
+    let send = move || {
+        if let Some(text) = clean_chat(&draft.get_untracked()) {
+            on_send.run(text);
+            draft.set(String::new());
+        }
+    };
+
+    view! {
+        <div class=move || if open.get() { "chat-panel open" } else { "chat-panel" }>
+            <button class="chat-header" on:click=move |_| open.update(|open| *open = !*open)>
+                <span class="toolbar-title">"Chat"</span>
+                <Show when=move || !open.get() && unread() > 0>
+                    <span class="chat-unread">{unread}</span>
+                </Show>
+                <span>{move || if open.get() { "▾" } else { "▴" }}</span>
+            </button>
+            <Show when=move || open.get()>
+                <div class="chat-messages" node_ref=list_ref>
+                    {move || {
+                        messages
+                            .get()
+                            .into_iter()
+                            .map(|message| {
+                                view! {
+                                    <div class="chat-message">
+                                        <span class="chat-time">{time_label(message.sent_at)}</span>
+                                        <span class="chat-author">{message.name}</span>
+                                        <span class="chat-text">{message.text}</span>
+                                    </div>
+                                }
+                            })
+                            .collect::<Vec<_>>()
+                    }}
+                </div>
+                <input
+                    class="chat-input"
+                    type="text"
+                    placeholder=move || if can_send.get() { "Message" } else { "Offline" }
+                    maxlength=MAX_CHAT_LEN.to_string()
+                    disabled=move || !can_send.get()
+                    prop:value=move || draft.get()
+                    on:input=move |ev| draft.set(event_target_value(&ev))
+                    on:keydown=move |ev: web_sys::KeyboardEvent| {
+                        // Typing here is text,not canvas shortcuts
+                        ev.stop_propagation();
+                        match ev.key().as_str() {
+                            "Enter" => send(),
+                            "Escape" => {
+                                let input: web_sys::HtmlInputElement = event_target(&ev);
+                                let _ = input.blur();
+                            }
+                            _ => {}
+                        }
+                    }
+                />
+            </Show>
+        </div>
+    }
+}
@@ -0,0 +1,262 @@
+use frontend::palette::{Palette, PaletteFormat, to_hex};
+use frontend::prelude::PixelColor;
+use leptos::ev::Event;
+use leptos::logging::error;
+use leptos::prelude::*;
+use leptos::task::spawn_local;
+use web_sys::MouseEvent;
+
+use crate::services::files;
+use crate::types::color::{Hsv, RecentColors};
+
+/// Palette files accepted by the import button
+const PALETTE_ACCEPT: &str = ".gpl,.hex,.json";
+
+fn pixel_color(hsv: Hsv, alpha: u8) -> PixelColor {
+    let [r, g, b] = hsv.to_rgb();
+    PixelColor::new(r, g, b, alpha)
+}
+
+/// Row of color swatches,right click is passed the index of the swatch
+#[component]
+fn Swatches(
+    #[prop(into)] colors: Signal<Vec<PixelColor>>,
+    #[prop(into)] on_pick: Callback<PixelColor>,
+    #[prop(optional, into)] on_remove: Option<Callback<usize>>,
+) -> impl IntoView {
+    view! {
+        <div class="color-grid">
+            {move || {
+                colors
+                    .get()
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, color)| {
+                        let hex = to_hex(&color.to_rgba());
+                        view! {
+                            <button
+                                class="color-button"
+                                title=hex.clone()
+                                style=format!("background-color: {hex}")
+                                on:click=move |_| on_pick.run(color)
+                                on:contextmenu=move |ev: MouseEvent| {
+                                    if let Some(on_remove) = on_remove {
+                                        ev.prevent_default();
+                                        on_remove.run(index);
+                                    }
+                                }
+                            />
+                        }
+                    })
+                    .collect::<Vec<_>>()
+            }}
+        </div>
+    }
+}
+
+/// HSV/hex color picker with the recent colors and the session palette.
+/// `on_pick` is called on every change,the color is added to the recent colors once it is settled.
+/// The palette is only changed through `on_palette_change`,so it can be shared with the session.
+#[component]
+pub fn ColorPicker(
+    #[prop(into)] color: Signal<PixelColor>,
+    #[prop(into)] on_pick: Callback<PixelColor>,
+    recent_colors: RwSignal<RecentColors>,
+    #[prop(into)] palette: Signal<Palette>,
+    #[prop(into)] on_palette_change: Callback<Palette>,
+) -> impl IntoView {
+    let hsv = RwSignal::new(Hsv::from_rgb([0, 0, 0]));
+    let alpha = RwSignal::new(255u8);
+    let current = move || pixel_color(hsv.get(), alpha.get());
+
+    // Follow the color of the tool,unless it is the one being edited here
+    // (gray colors would lose their hue otherwise)
+    Effect::new(move |_| {
+        let color = color.get();
+        if color != pixel_color(hsv.get_untracked(), alpha.get_untracked()) {
+            let [r, g, b, a] = color.to_rgba();
+            hsv.set(Hsv::from_rgb([r, g, b]));
+            alpha.set(a);
+        }
+    });
+
+    let pick = move |color: PixelColor| {
+        recent_colors.update(|recent| recent.push(color));
+        on_pick.run(color);
+    };
+    let edit = move |update: &dyn Fn(&mut Hsv, &mut u8)| {
+        let (mut new_hsv, mut new_alpha) = (hsv.get_untracked(), alpha.get_untracked());
+        update(&mut new_hsv, &mut new_alpha);
+        hsv.set(new_hsv);
+        alpha.set(new_alpha);
+        on_pick.run(pixel_color(new_hsv, new_alpha));
+    };
+    // Slider released,the color is settled
+    let settle = move |_: Event| recent_colors.update(|recent| recent.push(current()));
+
+    let set_hex = move |ev: Event| {
+        let Some([r, g, b, a]) = frontend::palette::parse_hex(&event_target_value(&ev)) else {
+            return;
+        };
+        // A fully transparent color would draw nothing
+        let a = a.max(1);
+        hsv.set(Hsv::from_rgb([r, g, b]));
+        alpha.set(a);
+        pick(PixelColor::new(r, g, b, a));
+    };
+
+    let import_palette = move |ev: Event| {
+        let input: web_sys::HtmlInputElement = event_target(&ev);
+        let Some(file) = files::picked_file(&input) else {
+            return;
+        };
+        // Let the same file be picked again
+        input.set_value("");
+        spawn_local(async move {
+            let file_name = file.name();
+            let name = file_name.rsplit_once('.').map_or(file_name.as_str(), |(stem, _)| stem);
+            let palette = match PaletteFormat::from_file_name(&file_name) {
+                Some(format) => files::read_text(&file)
+                    .await
+                    .and_then(|text| Palette::parse(format, &text, name)),
+                None => Err(format!("unknown palette format: {file_name}")),
+            };
+            match palette {
+                Ok(palette) => on_palette_change.run(palette),
+                Err(err) => error!("Failed to import palette: {err}"),
+            }
+        });
+    };
+    let export_palette = move |format: PaletteFormat| {
+        let palette = palette.get_untracked();
+        let file_name = format!("{}.{}", palette.name, format.extension());
+        if let Err(err) = files::download(palette.export(format).as_bytes(), &file_name) {
+            error!("Failed to export palette: {err}");
+        }
+    };
+
+    view! {
+        <div class="color-picker-panel">
+            <div class="color-current">
+                <div class="color-preview" style=move || format!("background-color: {}", to_hex(&current().to_rgba()))/>
+                <input
+                    class="hex-input"
+                    type="text"
+                    maxlength="9"
+                    prop:value=move || to_hex(&current().to_rgba())
+                    on:change=set_hex
+                />
+            </div>
+
+            <label class="color-slider">
+                "H"
+                <input
+                    type="range" min="0" max="359"
+                    prop:value=move || hsv.get().h.round().to_string()
+                    on:input=move |ev| {
+                        let h = event_target_value(&ev).parse::<f64>().unwrap_or(0.0);
+                        edit(&|hsv, _| hsv.h = h);
+                    }
+                    on:change=settle
+                />
+            </label>
+            <label class="color-slider">
+                "S"
+                <input
+                    type="range" min="0" max="100"
+                    prop:value=move || (hsv.get().s * 100.0).round().to_string()
+                    on:input=move |ev| {
+                        let s = event_target_value(&ev).parse::<f64>().unwrap_or(0.0) / 100.0;
+                        edit(&|hsv, _| hsv.s = s);
+                    }
+                    on:change=settle
+                />
+            </label>
+            <label class="color-slider">
+                "V"
+                <input
+                    type="range" min="0" max="100"
+                    prop:value=move || (hsv.get().v * 100.0).round().to_string()
+                    on:input=move |ev| {
+                        let v = event_target_value(&ev).parse::<f64>().unwrap_or(0.0) / 100.0;
+                        edit(&|hsv, _| hsv.v = v);
+                    }
+                    on:change=settle
+                />
+            </label>
+            <label class="color-slider">
+                "A"
+                <input
+                    type="range" min="1" max="255"
+                    prop:value=move || alpha.get().to_string()
+                    on:input=move |ev| {
+                        let a = event_target_value(&ev).parse::<u8>().unwrap_or(255);
+                        edit(&|_, alpha| *alpha = a);
+                    }
+                    on:change=settle
+                />
+            </label>
+
+            <div class="color-row-title">"Recent"</div>
+            <Swatches
+                colors=Signal::derive(move || recent_colors.with(|recent| recent.iter().collect()))
+                on_pick=pick
+            />
+
+            <div class="color-row-title" title="Right click a swatch to remove it">
+                {move || palette.with(|palette| palette.name.clone())}
+            </div>
+            <Swatches
+                colors=Signal::derive(move || palette.with(|palette| palette.pixel_colors().collect()))
+                on_pick=pick
+                on_remove=move |index| {
+                    let mut palette = palette.get_untracked();
+                    palette.remove(index);
+                    on_palette_change.run(palette);
+                }
+            />
+
+            <div class="palette-actions">
+                <button
+                    class="palette-button"
+                    title="Add the current color to the palette"
+                    on:click=move |_| {
+                        let mut palette = palette.get_untracked();
+                        palette.add(current());
+                        on_palette_change.run(palette);
+                    }
+                >
+                    "+"
+                </button>
+                <label class="palette-button" title="Import a .gpl,.hex or Lospec .json palette">
+                    "Import"
+                    <input type="file" accept=PALETTE_ACCEPT hidden=true on:change=import_palette/>
+                </label>
+                <button
+                    class="palette-button"
+                    title="Back to the basic colors"
+                    on:click=move |_| on_palette_change.run(Palette::default())
+                >
+                    "Reset"
+                </button>
+            </div>
+            <div class="palette-actions">
+                "Export:"
+                {PaletteFormat::ALL
+                    .into_iter()
+                    .map(|format| {
+                        view! {
+                            <button
+                                class="palette-button"
+                                title=format.name()
+                                on:click=move |_| export_palette(format)
+                            >
+                                {format.extension()}
+                            </button>
+                        }
+                    })
+                    .collect::<Vec<_>>()}
+            </div>
+        </div>
+    }
+}
@@ -0,0 +1,172 @@
+use frontend::palette::parse_hex;
+use frontend::prelude::PixelColor;
+use leptos::logging::error;
+use leptos::prelude::*;
+
+use crate::services::files;
+use crate::types::export::{self, Crop, ExportOptions, MAX_EXPORT_SCALE, SheetLayout};
+use crate::types::pixel_canvas::PixelCanvas;
+
+const EXPORT_NAME: &str = "pixel_art";
+/// Solid background color until the user picks another one
+const DEFAULT_BACKGROUND: &str = "#ffffff";
+
+fn download_or_log(result: Result<Vec<u8>, String>, file_name: &str) {
+    if let Err(err) = result.and_then(|bytes| files::download(&bytes, file_name)) {
+        error!("Failed to download {file_name}: {err}");
+    }
+}
+
+/// Export dialog of the board: the active frame as png,every frame as a gif or a spritesheet.
+/// Scale,background,grid lines and crop apply to every format.
+#[component]
+pub fn ExportMenu(#[prop(into)] canvas: RwSignal<PixelCanvas>) -> impl IntoView {
+    let scale = RwSignal::new(1u32);
+    let solid_background = RwSignal::new(false);
+    let background_color = RwSignal::new(DEFAULT_BACKGROUND.to_string());
+    let grid_lines = RwSignal::new(false);
+    let crop = RwSignal::new(Crop::default());
+    let layout = RwSignal::new(SheetLayout::default());
+    let has_selection = Memo::new(move |_| canvas.with(|pc| pc.selection().is_some()));
+
+    let options = move || ExportOptions {
+        scale: scale.get_untracked(),
+        background: solid_background
+            .get_untracked()
+            .then(|| background_color.with_untracked(|hex| parse_hex(hex)))
+            .flatten()
+            .map(|[r, g, b, a]| PixelColor::new(r, g, b, a)),
+        grid_lines: grid_lines.get_untracked(),
+        crop: crop.get_untracked(),
+    };
+
+    let export_png = move |_: leptos::ev::MouseEvent| {
+        let bytes = canvas.with_untracked(|pc| pc.image_export(&options()));
+        download_or_log(bytes, &format!("{EXPORT_NAME}.png"));
+    };
+    let export_gif = move |_: leptos::ev::MouseEvent| {
+        let options = options();
+        let bytes = canvas.with_untracked(|pc| {
+            let frames = pc.frame_images();
+            let rect = pc.export_rect(options.crop, frames.iter().map(|(frame, _)| frame))?;
+            export::animated_gif(&frames, &options, &rect)
+        });
+        download_or_log(bytes, &format!("{EXPORT_NAME}.gif"));
+    };
+    let export_sheet = move |_: leptos::ev::MouseEvent| {
+        let options = options();
+        let image_name = format!("{EXPORT_NAME}_sheet.png");
+        let sheet = canvas.with_untracked(|pc| {
+            let frames = pc.frame_images();
+            let rect = pc.export_rect(options.crop, frames.iter().map(|(frame, _)| frame))?;
+            export::spritesheet(&frames, layout.get_untracked(), &options, &rect, &image_name)
+        });
+        match sheet {
+            Ok((png, atlas)) => {
+                download_or_log(Ok(png), &image_name);
+                download_or_log(
+                    Ok(atlas.to_json().into_bytes()),
+                    &format!("{EXPORT_NAME}_sheet.json"),
+                );
+            }
+            Err(err) => error!("Failed to export the spritesheet: {err}"),
+        }
+    };
+
+    view! {
+        <div class="export-menu">
+            <label class="export-option">
+                "Scale "
+                <input
+                    type="number" min="1" max=MAX_EXPORT_SCALE.to_string()
+                    prop:value=move || scale.get().to_string()
+                    on:change=move |ev| {
+                        if let Ok(value) = event_target_value(&ev).parse::<u32>() {
+                            scale.set(value.clamp(1, MAX_EXPORT_SCALE));
+                        }
+                    }
+                />
+                "×"
+            </label>
+            <div class="export-option">
+                <label>
+                    <input
+                        type="checkbox"
+                        prop:checked=move || solid_background.get()
+                        on:change=move |ev| solid_background.set(event_target_checked(&ev))
+                    />
+                    "Background"
+                </label>
+                <input
+                    type="color"
+                    disabled=move || !solid_background.get()
+                    prop:value=move || background_color.get()
+                    on:input=move |ev| background_color.set(event_target_value(&ev))
+                />
+            </div>
+            <label class="export-option" title="Space the cells apart with rounded corners,like on the screen">
+                <input
+                    type="checkbox"
+                    prop:checked=move || grid_lines.get()
+                    on:change=move |ev| grid_lines.set(event_target_checked(&ev))
+                />
+                "Grid lines"
+            </label>
+            <div class="export-option">
+                "Crop "
+                <select on:change=move |ev| {
+                    let value = event_target_value(&ev);
+                    if let Some(option) = Crop::ALL.into_iter().find(|option| option.name() == value) {
+                        crop.set(option);
+                    }
+                }>
+                    {Crop::ALL
+                        .into_iter()
+                        .map(|option| {
+                            view! {
+                                <option
+                                    value=option.name()
+                                    selected=move || crop.get() == option
+                                    disabled=move || option == Crop::Selection && !has_selection.get()
+                                >
+                                    {option.name()}
+                                </option>
+                            }
+                        })
+                        .collect::<Vec<_>>()}
+                </select>
+            </div>
+            <button class="palette-button" title="Active frame as png" on:click=export_png>
+                "PNG"
+            </button>
+            <button class="palette-button" title="Every frame as a looping gif" on:click=export_gif>
+                "GIF"
+            </button>
+            <div class="export-option">
+                {SheetLayout::ALL
+                    .into_iter()
+                    .map(|option| {
+                        view! {
+                            <label>
+                                <input
+                                    type="radio"
+                                    name="sheet-layout"
+                                    prop:checked=move || layout.get() == option
+                                    on:change=move |_| layout.set(option)
+                                />
+                                {option.name()}
+                            </label>
+                        }
+                    })
+                    .collect::<Vec<_>>()}
+            </div>
+            <button
+                class="palette-button"
+                title="Every frame on one png,with a json atlas of the frame rectangles"
+                on:click=export_sheet
+            >
+                "Spritesheet"
+            </button>
+        </div>
+    }
+}
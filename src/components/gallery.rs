@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+
+use frontend::protocol::{MAX_NAME_LEN, SessionListing};
+use leptos::logging::error;
+use leptos::prelude::*;
+use leptos::task::spawn_local;
+use wasm_bindgen::JsValue;
+use web_sys::js_sys;
+
+use crate::services::{links, sessions};
+use crate::types::recent::{DEFAULT_SESSION_NAME, RecentSession, RecentSessions};
+
+/// Local date and time of a unix timestamp in milliseconds
+fn date_label(millis: u64) -> String {
+    let date = js_sys::Date::new(&JsValue::from_f64(millis as f64));
+    date.to_locale_string("default", &JsValue::UNDEFINED).into()
+}
+
+/// Page of a session,with the key it was opened with so the role stays the same
+fn session_href(id: &str, key: Option<&str>) -> String {
+    match key {
+        Some(key) => links::session_link(id, key),
+        None => format!("/drawing/{id}"),
+    }
+}
+
+/// Png of the session,or a placeholder when the server doesn't have it yet
+fn thumbnail(src: Option<String>, href: String) -> impl IntoView {
+    view! {
+        <a class="gallery-thumbnail" href=href>
+            {match src {
+                Some(src) => view! { <img src=src alt="Preview" loading="lazy"/> }.into_any(),
+                None => view! { <span>"No preview"</span> }.into_any(),
+            }}
+        </a>
+    }
+}
+
+/// A session opened in this browser,with the size and last change given by the server when it has them
+fn recent_card(
+    session: RecentSession,
+    listing: Option<SessionListing>,
+    recent: RwSignal<RecentSessions>,
+) -> impl IntoView {
+    let href = session_href(&session.id, session.key.as_deref());
+    let size = listing
+        .as_ref()
+        .map(|listing| (listing.width as usize, listing.height as usize));
+    let (width, height) = size.unwrap_or((session.width, session.height));
+    let when = match &listing {
+        Some(listing) => format!("Changed {}", date_label(listing.modified)),
+        None => format!("Opened {}", date_label(session.visited_at)),
+    };
+    let src = size.map(|(width, height)| sessions::thumbnail_url(&session.id, width, height));
+    let rename_id = session.id.clone();
+    let delete_id = session.id;
+
+    view! {
+        <div class="gallery-card">
+            {thumbnail(src, href.clone())}
+            <input
+                class="gallery-name"
+                type="text"
+                title="Rename,only in this browser"
+                maxlength=MAX_NAME_LEN.to_string()
+                prop:value=session.name
+                on:change=move |ev| {
+                    // Rejected names are replaced by the current one when the list is drawn again
+                    recent.update(|recent| {
+                        recent.rename(&rename_id, &event_target_value(&ev));
+                    })
+                }
+            />
+            <span class="gallery-meta">{format!("{width}×{height} · {when}")}</span>
+            <div class="gallery-actions">
+                <a class="palette-button" href=href>"Open"</a>
+                <button
+                    class="palette-button"
+                    title="Forget this canvas,it stays on the server"
+                    on:click=move |_| recent.update(|recent| recent.remove(&delete_id))
+                >
+                    "🗑 Delete"
+                </button>
+            </div>
+        </div>
+    }
+}
+
+/// A session the server lists publicly
+fn public_card(listing: SessionListing, key: Option<String>) -> impl IntoView {
+    let href = session_href(&listing.id, key.as_deref());
+    let (width, height) = (listing.width as usize, listing.height as usize);
+    let src = sessions::thumbnail_url(&listing.id, width, height);
+    let name = listing
+        .name
+        .unwrap_or_else(|| DEFAULT_SESSION_NAME.to_string());
+    let meta = format!("{width}×{height} · Changed {}", date_label(listing.modified));
+
+    view! {
+        <div class="gallery-card">
+            {thumbnail(Some(src), href.clone())}
+            <span class="gallery-name">{name}</span>
+            <span class="gallery-meta">{meta}</span>
+            <div class="gallery-actions">
+                <a class="palette-button" href=href>"Open"</a>
+            </div>
+        </div>
+    }
+}
+
+/// Canvases opened in this browser,then the public canvases of the server
+#[component]
+pub fn Gallery() -> impl IntoView {
+    let recent = RwSignal::new(RecentSessions::load());
+    // Size and last change of the recent sessions,by id,as the server answers
+    let listings = RwSignal::new(HashMap::<String, SessionListing>::new());
+    let public = RwSignal::new(Vec::<SessionListing>::new());
+
+    spawn_local(async move {
+        match sessions::public_sessions().await {
+            Ok(found) => public.set(found),
+            Err(err) => error!("Couldn't list the public sessions: {err}"),
+        }
+    });
+    let ids: Vec<String> =
+        recent.with_untracked(|recent| recent.iter().map(|session| session.id.clone()).collect());
+    for id in ids {
+        spawn_local(async move {
+            match sessions::session_listing(&id).await {
+                Ok(Some(listing)) => listings.update(|listings| {
+                    listings.insert(id, listing);
+                }),
+                // Never saved by the server,or removed from it
+                Ok(None) => {}
+                Err(err) => error!("Couldn't get session {id}: {err}"),
+            }
+        });
+    }
+
+    view! {
+        <div class="gallery-section">
+            <h2>"Your Canvases"</h2>
+            <Show
+                when=move || recent.with(|recent| !recent.is_empty())
+                fallback=|| view! { <p class="gallery-empty">"The canvases you open are listed here."</p> }
+            >
+                <div class="gallery-grid">
+                    {move || {
+                        recent
+                            .with(|list| {
+                                list
+                                    .iter()
+                                    .map(|session| {
+                                        let listing = listings
+                                            .with(|listings| listings.get(&session.id).cloned());
+                                        recent_card(session.clone(), listing, recent)
+                                    })
+                                    .collect::<Vec<_>>()
+                            })
+                    }}
+                </div>
+            </Show>
+            <h2>"Public Canvases"</h2>
+            <Show
+                when=move || public.with(|public| !public.is_empty())
+                fallback=|| view! { <p class="gallery-empty">"No public canvas yet."</p> }
+            >
+                <div class="gallery-grid">
+                    {move || {
+                        public
+                            .with(|public| {
+                                public
+                                    .iter()
+                                    .map(|listing| {
+                                        let key = recent
+                                            .with(|list| {
+                                                list.get(&listing.id).and_then(|session| session.key.clone())
+                                            });
+                                        public_card(listing.clone(), key)
+                                    })
+                                    .collect::<Vec<_>>()
+                            })
+                    }}
+                </div>
+            </Show>
+        </div>
+    }
+}
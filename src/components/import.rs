@@ -0,0 +1,57 @@
+use frontend::palette::Palette;
+use leptos::ev::Event;
+use leptos::logging::error;
+use leptos::prelude::*;
+use leptos::task::spawn_local;
+
+use crate::services::files;
+use crate::types::{pixel_canvas::PixelCanvas, quantize};
+
+/// Image files accepted by the import button
+const IMAGE_ACCEPT: &str = "image/png,image/jpeg";
+
+/// Load a png or jpeg onto the active layer,fitted to the grid and reduced to the session palette
+#[component]
+pub fn ImportMenu(
+    #[prop(into)] canvas: RwSignal<PixelCanvas>,
+    #[prop(into)] palette: Signal<Palette>,
+) -> impl IntoView {
+    let dither = RwSignal::new(false);
+
+    let import_image = move |ev: Event| {
+        let input: web_sys::HtmlInputElement = event_target(&ev);
+        let Some(file) = files::picked_file(&input) else {
+            return;
+        };
+        // Let the same file be picked again
+        input.set_value("");
+        spawn_local(async move {
+            let colors: Vec<_> = palette.with_untracked(|palette| palette.pixel_colors().collect());
+            let grid_dimension = canvas.with_untracked(|pc| pc.grid_dimension());
+            let region = files::read_bytes(&file).await.and_then(|bytes| {
+                quantize::import_image(&bytes, grid_dimension, &colors, dither.get_untracked())
+            });
+            match region {
+                Ok(region) => canvas.update(|pc| pc.import_region(&region)),
+                Err(err) => error!("Failed to import {}: {err}", file.name()),
+            }
+        });
+    };
+
+    view! {
+        <div class="export-menu">
+            <label class="export-option" title="Spread the color error on the neighbour pixels (Floyd–Steinberg)">
+                <input
+                    type="checkbox"
+                    prop:checked=move || dither.get()
+                    on:change=move |ev| dither.set(event_target_checked(&ev))
+                />
+                "Dithering"
+            </label>
+            <label class="palette-button" title="Load a png or jpeg onto the active layer,with the palette colors">
+                "Load image"
+                <input type="file" accept=IMAGE_ACCEPT hidden=true on:change=import_image/>
+            </label>
+        </div>
+    }
+}
@@ -0,0 +1,187 @@
+use frontend::layers::{LayerInfo, LayerOp, MAX_LAYERS};
+use leptos::logging::error;
+use leptos::prelude::*;
+
+use crate::services::files;
+use crate::types::pixel_canvas::PixelCanvas;
+
+/// Name of the png of a layer,only letters,digits,`-` and `_` are kept from the layer name
+fn layer_file_name(name: &str) -> String {
+    let kept = |c: char| c.is_alphanumeric() || c == '-';
+    let name: String = name
+        .trim()
+        .chars()
+        .map(|c| if kept(c) { c } else { '_' })
+        .collect();
+    format!("pixel_art_{name}.png")
+}
+
+/// One row of the layers panel
+#[component]
+fn LayerRow(
+    info: LayerInfo,
+    /// Position in the stack,0 is the bottom
+    index: usize,
+    layer_count: usize,
+    canvas: RwSignal<PixelCanvas>,
+    on_layer_op: Callback<LayerOp>,
+) -> impl IntoView {
+    let id = info.id;
+    let is_active = move || canvas.with(|pc| pc.active_layer() == id);
+    let visible = info.visible;
+    let locked = info.locked;
+    let name = info.name.clone();
+    let export = move |ev: leptos::ev::MouseEvent| {
+        ev.stop_propagation();
+        let result = canvas
+            .with_untracked(|pc| pc.layer_export(id))
+            .and_then(|bytes| files::download(&bytes, &layer_file_name(&name)));
+        if let Err(err) = result {
+            error!("Failed to export layer {name}: {err}");
+        }
+    };
+
+    view! {
+        <div
+            class=move || if is_active() { "layer-row active" } else { "layer-row" }
+            on:click=move |_| canvas.update(|pc| pc.set_active_layer(id))
+        >
+            <button
+                class="layer-toggle"
+                title=if visible { "Hide" } else { "Show" }
+                on:click=move |ev| {
+                    ev.stop_propagation();
+                    on_layer_op.run(LayerOp::SetVisible { id, visible: !visible });
+                }
+            >
+                {if visible { "👁" } else { "◌" }}
+            </button>
+            <button
+                class="layer-toggle"
+                title=if locked { "Unlock" } else { "Lock" }
+                on:click=move |ev| {
+                    ev.stop_propagation();
+                    on_layer_op.run(LayerOp::SetLocked { id, locked: !locked });
+                }
+            >
+                {if locked { "🔒" } else { "🔓" }}
+            </button>
+            <input
+                class="layer-name"
+                type="text"
+                prop:value=info.name.clone()
+                on:click=move |ev| ev.stop_propagation()
+                on:change=move |ev| {
+                    on_layer_op.run(LayerOp::Rename { id, name: event_target_value(&ev) });
+                }
+            />
+            <input
+                class="layer-opacity"
+                type="range" min="0" max="255"
+                title=format!("Opacity {}%", info.opacity as u32 * 100 / 255)
+                prop:value=info.opacity.to_string()
+                on:click=move |ev| ev.stop_propagation()
+                on:change=move |ev| {
+                    if let Ok(opacity) = event_target_value(&ev).parse() {
+                        on_layer_op.run(LayerOp::SetOpacity { id, opacity });
+                    }
+                }
+            />
+            <button
+                class="layer-toggle"
+                title="Move up"
+                disabled=index + 1 >= layer_count
+                on:click=move |ev| {
+                    ev.stop_propagation();
+                    on_layer_op.run(LayerOp::Move { id, index: index + 1 });
+                }
+            >
+                "▲"
+            </button>
+            <button
+                class="layer-toggle"
+                title="Move down"
+                disabled=index == 0
+                on:click=move |ev| {
+                    ev.stop_propagation();
+                    on_layer_op.run(LayerOp::Move { id, index: index - 1 });
+                }
+            >
+                "▼"
+            </button>
+            <button class="layer-toggle" title="Download this layer as a png" on:click=export>
+                "📥"
+            </button>
+            <button
+                class="layer-toggle"
+                title="Delete"
+                disabled=layer_count == 1
+                on:click=move |ev| {
+                    ev.stop_propagation();
+                    on_layer_op.run(LayerOp::Remove { id });
+                }
+            >
+                "✕"
+            </button>
+        </div>
+    }
+}
+
+/// Layers of the board,top layer first,with the active layer highlighted
+#[component]
+pub fn LayersPanel(
+    #[prop(into)] canvas: RwSignal<PixelCanvas>,
+    #[prop(into)] on_layer_op: Callback<LayerOp>,
+) -> impl IntoView {
+    // Only rebuild the rows when the layer structure changes,not on every stroke
+    let infos = Memo::new(move |_| {
+        canvas.with(|pc| {
+            pc.layers()
+                .layers()
+                .iter()
+                .map(|layer| layer.info.clone())
+                .collect::<Vec<_>>()
+        })
+    });
+
+    view! {
+        <div class="layers-panel">
+            <div class="layers-header">
+                <span class="toolbar-title">"Layers"</span>
+                <button
+                    class="palette-button"
+                    title="Add a layer on top"
+                    disabled=move || infos.with(|infos| infos.len() >= MAX_LAYERS)
+                    on:click=move |_| {
+                        let name = format!("Layer {}", infos.with_untracked(|infos| infos.len()) + 1);
+                        on_layer_op.run(LayerOp::Add { id: 0, name });
+                    }
+                >
+                    "+"
+                </button>
+            </div>
+            <div class="layer-rows">
+                {move || {
+                    let infos = infos.get();
+                    let layer_count = infos.len();
+                    infos
+                        .into_iter()
+                        .enumerate()
+                        .rev()
+                        .map(|(index, info)| {
+                            view! {
+                                <LayerRow
+                                    info=info
+                                    index=index
+                                    layer_count=layer_count
+                                    canvas=canvas
+                                    on_layer_op=on_layer_op
+                                />
+                            }
+                        })
+                        .collect::<Vec<_>>()
+                }}
+            </div>
+        </div>
+    }
+}
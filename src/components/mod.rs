@@ -1,5 +1,22 @@
 pub mod canvas;
+pub mod chat;
+pub mod color_picker;
+pub mod export;
+pub mod gallery;
+pub mod import;
+pub mod layers;
+pub mod owner;
+pub mod participants;
+pub mod status;
+pub mod timeline;
 pub mod toolbar;
 
 pub use canvas::Canvas;
+pub use chat::ChatPanel;
+pub use gallery::Gallery;
+pub use layers::LayersPanel;
+pub use owner::OwnerControls;
+pub use participants::ParticipantsPanel;
+pub use status::{ConnectionStatus, StatusBadge};
+pub use timeline::Timeline;
 pub use toolbar::{Toolbar, DrawingTool};
\ No newline at end of file
@@ -0,0 +1,199 @@
+use frontend::board::{Anchor, MAX_BOARD_SIDE};
+use frontend::protocol::{MAX_NAME_LEN, OwnerAction, clean_name};
+use leptos::prelude::*;
+
+use crate::types::selection::GridRect;
+
+/// Side typed in a size input,None if it isn't a valid board side
+fn parse_side(value: &str) -> Option<usize> {
+    value
+        .parse()
+        .ok()
+        .filter(|side| (1..=MAX_BOARD_SIDE).contains(side))
+}
+
+/// Controls of the session owner: lock the board,clear it,change its size,crop it to the selection
+/// or list it on the home page of everyone.
+/// Every action goes through the server,which applies it for everyone.
+#[component]
+pub fn OwnerControls(
+    #[prop(into)] locked: Signal<bool>,
+    /// Width and height of the board
+    #[prop(into)] dimension: Signal<(usize, usize)>,
+    /// Selected cells,the board can be cropped to them
+    #[prop(into)] selection: Signal<Option<GridRect>>,
+    /// Name the session is publicly listed under
+    #[prop(into)] published: Signal<Option<String>>,
+    #[prop(into)] on_action: Callback<OwnerAction>,
+) -> impl IntoView {
+    let width = RwSignal::new(0usize);
+    let height = RwSignal::new(0usize);
+    let anchor = RwSignal::new(Anchor::default());
+    let public_name = RwSignal::new(String::new());
+    Effect::new(move |_| public_name.set(published.get().unwrap_or_default()));
+    // Start from the size of the board,again whenever it changes
+    Effect::new(move |_| {
+        let (board_width, board_height) = dimension.get();
+        width.set(board_width);
+        height.set(board_height);
+    });
+
+    let clear = move |_| {
+        let confirmed = window()
+            .confirm_with_message("Erase every layer of every frame for everyone?")
+            .unwrap_or(false);
+        if confirmed {
+            on_action.run(OwnerAction::Clear);
+        }
+    };
+    let resize = move |_| {
+        let (width, height) = (width.get_untracked(), height.get_untracked());
+        if (width, height) == dimension.get_untracked() {
+            return;
+        }
+        on_action.run(OwnerAction::Resize {
+            width: width as u32,
+            height: height as u32,
+            anchor: anchor.get_untracked(),
+        });
+    };
+    let crop = move |_| {
+        let Some(rect) = selection.get_untracked() else {
+            return;
+        };
+        on_action.run(OwnerAction::Crop {
+            x: rect.left as u32,
+            y: rect.top as u32,
+            width: rect.width as u32,
+            height: rect.height as u32,
+        });
+    };
+    let publish = move |_| {
+        if let Some(name) = clean_name(&public_name.get_untracked()) {
+            on_action.run(OwnerAction::Publish { name: Some(name) });
+        }
+    };
+    // Cropping to the whole board changes nothing
+    let can_crop = move || {
+        let (width, height) = dimension.get();
+        let whole = GridRect {
+            left: 0,
+            top: 0,
+            width,
+            height,
+        };
+        selection.with(|rect| rect.as_ref().is_some_and(|rect| *rect != whole))
+    };
+
+    view! {
+        <div class="owner-controls">
+            <div class="participant-row">
+                <button
+                    class="palette-button"
+                    title="Only you can draw while the board is locked"
+                    on:click=move |_| {
+                        on_action.run(OwnerAction::SetLocked {
+                            locked: !locked.get_untracked(),
+                        })
+                    }
+                >
+                    {move || if locked.get() { "🔓 Unlock" } else { "🔒 Lock" }}
+                </button>
+                <button class="palette-button" title="Erase the board for everyone" on:click=clear>
+                    "🧽 Clear"
+                </button>
+            </div>
+            <div class="participant-row">
+                <input
+                    class="owner-size"
+                    type="number"
+                    min="1"
+                    max=MAX_BOARD_SIDE.to_string()
+                    prop:value=move || width.get().to_string()
+                    on:change=move |ev| match parse_side(&event_target_value(&ev)) {
+                        Some(side) => width.set(side),
+                        None => width.notify(),
+                    }
+                />
+                "×"
+                <input
+                    class="owner-size"
+                    type="number"
+                    min="1"
+                    max=MAX_BOARD_SIDE.to_string()
+                    prop:value=move || height.get().to_string()
+                    on:change=move |ev| match parse_side(&event_target_value(&ev)) {
+                        Some(side) => height.set(side),
+                        None => height.notify(),
+                    }
+                />
+                <button
+                    class="palette-button"
+                    title="Change the size of the board,the pixels stay at the anchor"
+                    on:click=resize
+                >
+                    "📐 Resize"
+                </button>
+            </div>
+            <div class="participant-row">
+                <div class="anchor-grid" title="Part of the board kept in place by a resize">
+                    {Anchor::ALL
+                        .into_iter()
+                        .map(|option| {
+                            view! {
+                                <button
+                                    class=move || {
+                                        if anchor.get() == option {
+                                            "anchor-button active"
+                                        } else {
+                                            "anchor-button"
+                                        }
+                                    }
+                                    title=option.name()
+                                    on:click=move |_| anchor.set(option)
+                                >
+                                    {option.arrow()}
+                                </button>
+                            }
+                        })
+                        .collect::<Vec<_>>()}
+                </div>
+                <button
+                    class="palette-button"
+                    title="Keep only the selected cells"
+                    disabled=move || !can_crop()
+                    on:click=crop
+                >
+                    "✂ Crop to selection"
+                </button>
+            </div>
+            <div class="participant-row">
+                <input
+                    class="layer-name"
+                    type="text"
+                    placeholder="Public name"
+                    title="Name shown to everyone on the home page"
+                    maxlength=MAX_NAME_LEN.to_string()
+                    prop:value=move || public_name.get()
+                    on:input=move |ev| public_name.set(event_target_value(&ev))
+                />
+                <button
+                    class="palette-button"
+                    title="List this board on the home page of everyone"
+                    on:click=publish
+                >
+                    {move || if published.with(Option::is_some) { "🌐 Rename" } else { "🌐 Publish" }}
+                </button>
+                <Show when=move || published.with(Option::is_some)>
+                    <button
+                        class="palette-button"
+                        title="Stop listing this board"
+                        on:click=move |_| on_action.run(OwnerAction::Publish { name: None })
+                    >
+                        "Unlist"
+                    </button>
+                </Show>
+            </div>
+        </div>
+    }
+}
@@ -0,0 +1,133 @@
+use frontend::protocol::{MAX_NAME_LEN, Role, SessionKeys, clean_name};
+use leptos::logging::{error, log};
+use leptos::prelude::*;
+use leptos::task::spawn_local;
+
+use crate::services::{clipboard, links};
+use crate::types::presence::{Participants, cursor_color};
+
+/// Copy the link of a session page to the clipboard
+fn copy_link(session_id: &str, key: &str) {
+    let link = links::session_link(session_id, key);
+    spawn_local(async move {
+        match clipboard::write_text(&link).await {
+            Ok(()) => log!("Copied {link}"),
+            Err(err) => error!("Couldn't copy {link}: {err}"),
+        }
+    });
+}
+
+/// People connected to the session with the color of their cursor,
+/// the first row edits the display name of this user.
+/// Editors can copy the editor and viewer links of the session,the owner can remove people.
+#[component]
+pub fn ParticipantsPanel(
+    #[prop(into)] participants: Signal<Participants>,
+    #[prop(into)] display_name: RwSignal<String>,
+    session_id: String,
+    #[prop(into)] role: Signal<Role>,
+    /// Only the owner may draw
+    #[prop(into)] locked: Signal<bool>,
+    /// Keys of the session links,only known by editors
+    #[prop(into)] keys: Signal<Option<SessionKeys>>,
+    /// Remove a participant,by client id
+    #[prop(into)] on_kick: Callback<u64>,
+    /// Shown at the bottom,like the owner controls
+    #[prop(optional)] children: Option<Children>,
+) -> impl IntoView {
+    let session_id = StoredValue::new(session_id);
+    let copy_key = move |pick: fn(&SessionKeys) -> &str| {
+        keys.with_untracked(|keys| {
+            if let Some(keys) = keys {
+                session_id.with_value(|session_id| copy_link(session_id, pick(keys)));
+            }
+        });
+    };
+    let count = move || participants.with(|participants| participants.others().count() + 1);
+    let my_color = move || {
+        participants.with(|participants| participants.me().map(cursor_color).unwrap_or_default())
+    };
+
+    view! {
+        <div class="participants-panel">
+            <div class="layers-header">
+                <span class="toolbar-title">{move || format!("People ({})", count())}</span>
+            </div>
+            <div class="participant-row">
+                <span class="participant-color" style:background=my_color></span>
+                <input
+                    class="layer-name"
+                    type="text"
+                    title="Your name,shown to the others"
+                    maxlength=MAX_NAME_LEN.to_string()
+                    prop:value=move || display_name.get()
+                    on:change=move |ev| {
+                        match clean_name(&event_target_value(&ev)) {
+                            Some(name) => display_name.set(name),
+                            // Put the current name back in the input
+                            None => display_name.notify(),
+                        }
+                    }
+                />
+                <span class="participant-tool">
+                    {move || match role.get() {
+                        Role::Owner => "(you, owner)",
+                        Role::Editor if locked.get() => "(you, locked)",
+                        Role::Editor => "(you)",
+                        Role::Viewer => "(you, viewing)",
+                    }}
+                </span>
+            </div>
+            {move || {
+                participants
+                    .with(|participants| {
+                        participants
+                            .others()
+                            .map(|(client, participant)| {
+                                view! {
+                                    <div class="participant-row">
+                                        <span
+                                            class="participant-color"
+                                            style:background=cursor_color(client)
+                                        ></span>
+                                        <span class="participant-name">{participant.name.clone()}</span>
+                                        <span class="participant-tool">
+                                            {participant.presence.tool.clone()}
+                                        </span>
+                                        <Show when=move || role.get() == Role::Owner>
+                                            <button
+                                                class="layer-toggle"
+                                                title="Remove from the session"
+                                                on:click=move |_| on_kick.run(client)
+                                            >
+                                                "✕"
+                                            </button>
+                                        </Show>
+                                    </div>
+                                }
+                            })
+                            .collect::<Vec<_>>()
+                    })
+            }}
+            <Show when=move || keys.with(Option::is_some)>
+                <div class="participant-row">
+                    <button
+                        class="palette-button"
+                        title="Copy a link letting others draw on this board"
+                        on:click=move |_| copy_key(|keys| &keys.editor)
+                    >
+                        "🔗 Editor link"
+                    </button>
+                    <button
+                        class="palette-button"
+                        title="Copy a link to watch this board without changing it"
+                        on:click=move |_| copy_key(|keys| &keys.viewer)
+                    >
+                        "👁 Viewer link"
+                    </button>
+                </div>
+            </Show>
+            {children.map(|children| children())}
+        </div>
+    }
+}
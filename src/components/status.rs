@@ -0,0 +1,47 @@
+use leptos::prelude::*;
+
+/// State of the session websocket,as shown to the user
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionStatus {
+    /// First connection attempt
+    Connecting,
+    Connected,
+    /// Connection lost,waiting before the next attempt
+    Disconnected,
+    /// Connection lost,an attempt is in progress
+    Reconnecting,
+    /// The owner removed this user,no reconnect is attempted
+    Removed,
+}
+
+impl ConnectionStatus {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ConnectionStatus::Connecting => "Connecting...",
+            ConnectionStatus::Connected => "Connected",
+            ConnectionStatus::Disconnected => "Disconnected",
+            ConnectionStatus::Reconnecting => "Reconnecting...",
+            ConnectionStatus::Removed => "Removed by the owner",
+        }
+    }
+
+    fn class(&self) -> &'static str {
+        match self {
+            ConnectionStatus::Connecting => "status-badge connecting",
+            ConnectionStatus::Connected => "status-badge connected",
+            ConnectionStatus::Disconnected | ConnectionStatus::Removed => "status-badge disconnected",
+            ConnectionStatus::Reconnecting => "status-badge reconnecting",
+        }
+    }
+}
+
+/// Small badge showing if the drawing is synchronized with the server
+#[component]
+pub fn StatusBadge(#[prop(into)] status: Signal<ConnectionStatus>) -> impl IntoView {
+    view! {
+        <div class=move || status.get().class()>
+            <span class="status-dot"></span>
+            <span class="status-label">{move || status.get().label()}</span>
+        </div>
+    }
+}
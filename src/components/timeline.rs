@@ -0,0 +1,233 @@
+use std::time::Duration;
+
+use frontend::board::{FrameOp, MAX_FRAMES};
+use frontend::prelude::DrawingPixelCanvas;
+use leptos::html;
+use leptos::logging::error;
+use leptos::prelude::*;
+use wasm_bindgen::{Clamped, JsCast};
+use web_sys::{CanvasRenderingContext2d, ImageData};
+
+use crate::types::{export::rgba_bytes, pixel_canvas::PixelCanvas};
+
+/// How often the playback checks if the next frame is due
+const PLAYBACK_TICK: Duration = Duration::from_millis(10);
+
+/// Draw a frame one canvas pixel per grid cell,css scales it up
+fn draw_preview(canvas: &web_sys::HtmlCanvasElement, frame: &DrawingPixelCanvas) -> Result<(), String> {
+    let (width, height) = frame.dimension();
+    canvas.set_width(width as u32);
+    canvas.set_height(height as u32);
+    let context = canvas
+        .get_context("2d")
+        .map_err(|_| "Failed to get preview context".to_string())?
+        .ok_or("Preview context is null".to_string())?
+        .dyn_into::<CanvasRenderingContext2d>()
+        .map_err(|_| "Failed to cast to CanvasRenderingContext2d".to_string())?;
+    let bytes = rgba_bytes(frame);
+    let image = ImageData::new_with_u8_clamped_array_and_sh(Clamped(&bytes), width as u32, height as u32)
+        .map_err(|_| "Failed to create the preview image".to_string())?;
+    context
+        .put_image_data(&image, 0.0, 0.0)
+        .map_err(|_| "Failed to draw the preview".to_string())
+}
+
+/// Frames of the board in playback order,with the controls of the active frame and a playback preview
+#[component]
+pub fn Timeline(
+    #[prop(into)] canvas: RwSignal<PixelCanvas>,
+    #[prop(into)] on_frame_op: Callback<FrameOp>,
+    /// View mode: frames can be browsed and played but not changed
+    #[prop(into)] read_only: Signal<bool>,
+) -> impl IntoView {
+    // Only rebuild the strip when the frames change,not on every stroke
+    let frames = Memo::new(move |_| {
+        canvas.with(|pc| {
+            pc.frames()
+                .iter()
+                .map(|frame| (frame.id, frame.duration_ms))
+                .collect::<Vec<_>>()
+        })
+    });
+    let active_frame = Memo::new(move |_| canvas.with(|pc| pc.active_frame()));
+    let active_index = move || {
+        let active = active_frame.get();
+        frames.with(|frames| frames.iter().position(|(id, _)| *id == active).unwrap_or(0))
+    };
+    let active_duration = move || {
+        let active = active_frame.get();
+        frames.with(|frames| {
+            frames
+                .iter()
+                .find(|(id, _)| *id == active)
+                .map(|(_, duration_ms)| *duration_ms)
+                .unwrap_or_default()
+        })
+    };
+    let onion_skin = Memo::new(move |_| canvas.with(|pc| pc.onion_skin()));
+
+    //region playback
+    let playing = RwSignal::new(false);
+    // Index of the frame shown by the preview while playing
+    let play_index = RwSignal::new(0usize);
+    // Time the current frame has been shown
+    let elapsed = StoredValue::new(Duration::ZERO);
+    set_interval(
+        move || {
+            if !playing.get_untracked() {
+                return;
+            }
+            elapsed.update_value(|elapsed| *elapsed += PLAYBACK_TICK);
+            let index = play_index.get_untracked();
+            let (duration_ms, frame_count) = frames.with_untracked(|frames| {
+                (frames.get(index).map_or(0, |(_, duration_ms)| *duration_ms), frames.len())
+            });
+            if elapsed.get_value() >= Duration::from_millis(duration_ms as u64) {
+                elapsed.set_value(Duration::ZERO);
+                play_index.set((index + 1) % frame_count.max(1));
+            }
+        },
+        PLAYBACK_TICK,
+    );
+    let toggle_playback = move |_: leptos::ev::MouseEvent| {
+        if !playing.get_untracked() {
+            play_index.set(active_index());
+            elapsed.set_value(Duration::ZERO);
+        }
+        playing.update(|playing| *playing = !*playing);
+    };
+
+    let preview_ref = NodeRef::<html::Canvas>::new();
+    Effect::new(move |_| {
+        let Some(preview) = preview_ref.get() else {
+            return;
+        };
+        let frame_id = if playing.get() {
+            frames.with(|frames| frames.get(play_index.get()).map(|(id, _)| *id))
+        } else {
+            Some(active_frame.get())
+        };
+        let Some(pixels) = frame_id.and_then(|id| canvas.with(|pc| pc.rendered_frame(id))) else {
+            return;
+        };
+        if let Err(err) = draw_preview(&preview, &pixels) {
+            error!("{err}");
+        }
+    });
+    //endregion
+
+    let add_frame = move |copy: bool| {
+        let active = active_frame.get_untracked();
+        on_frame_op.run(FrameOp::Add {
+            id: 0,
+            index: active_index() + 1,
+            copy_of: copy.then_some(active),
+        });
+    };
+
+    view! {
+        <div class="timeline-panel">
+            <div class="timeline-controls">
+                <span class="toolbar-title">"Frames"</span>
+                <Show when=move || !read_only.get()>
+                    <button
+                        class="palette-button"
+                        title="Add an empty frame after this one"
+                        disabled=move || frames.with(|frames| frames.len() >= MAX_FRAMES)
+                        on:click=move |_| add_frame(false)
+                    >
+                        "+"
+                    </button>
+                    <button
+                        class="palette-button"
+                        title="Duplicate this frame"
+                        disabled=move || frames.with(|frames| frames.len() >= MAX_FRAMES)
+                        on:click=move |_| add_frame(true)
+                    >
+                        "⧉"
+                    </button>
+                    <button
+                        class="palette-button"
+                        title="Move earlier"
+                        disabled=move || active_index() == 0
+                        on:click=move |_| {
+                            let id = active_frame.get_untracked();
+                            on_frame_op.run(FrameOp::Move { id, index: active_index() - 1 });
+                        }
+                    >
+                        "◀"
+                    </button>
+                    <button
+                        class="palette-button"
+                        title="Move later"
+                        disabled=move || active_index() + 1 >= frames.with(|frames| frames.len())
+                        on:click=move |_| {
+                            let id = active_frame.get_untracked();
+                            on_frame_op.run(FrameOp::Move { id, index: active_index() + 1 });
+                        }
+                    >
+                        "▶"
+                    </button>
+                    <button
+                        class="palette-button"
+                        title="Delete this frame"
+                        disabled=move || frames.with(|frames| frames.len() == 1)
+                        on:click=move |_| on_frame_op.run(FrameOp::Remove { id: active_frame.get_untracked() })
+                    >
+                        "✕"
+                    </button>
+                    <label class="frame-duration" title="How long the frame is shown">
+                        <input
+                            type="number" min="10" max="10000" step="10"
+                            prop:value=move || active_duration().to_string()
+                            on:change=move |ev| {
+                                if let Ok(duration_ms) = event_target_value(&ev).parse::<u32>() {
+                                    let id = active_frame.get_untracked();
+                                    on_frame_op.run(FrameOp::SetDuration { id, duration_ms });
+                                }
+                            }
+                        />
+                        "ms"
+                    </label>
+                </Show>
+                <button
+                    class=move || if onion_skin.get() { "palette-button active" } else { "palette-button" }
+                    title="Onion skin: show the previous and next frames"
+                    on:click=move |_| canvas.update(|pc| pc.set_onion_skin(!pc.onion_skin()))
+                >
+                    "🧅"
+                </button>
+                <button
+                    class="palette-button"
+                    title=move || if playing.get() { "Pause" } else { "Play" }
+                    on:click=toggle_playback
+                >
+                    {move || if playing.get() { "⏸" } else { "⏵" }}
+                </button>
+            </div>
+            <div class="timeline-strip">
+                {move || {
+                    frames
+                        .get()
+                        .into_iter()
+                        .enumerate()
+                        .map(|(index, (id, duration_ms))| {
+                            view! {
+                                <button
+                                    class=move || {
+                                        if active_frame.get() == id { "frame-cell active" } else { "frame-cell" }
+                                    }
+                                    title=format!("{duration_ms} ms")
+                                    on:click=move |_| canvas.update(|pc| pc.set_active_frame(id))
+                                >
+                                    {index + 1}
+                                </button>
+                            }
+                        })
+                        .collect::<Vec<_>>()
+                }}
+            </div>
+            <canvas class="timeline-preview" node_ref=preview_ref></canvas>
+        </div>
+    }
+}
@@ -1,3 +1,4 @@
+use frontend::palette::Palette;
 use frontend::prelude::PixelColor;
 use leptos::ev;
 use leptos::html;
@@ -5,34 +6,25 @@ use leptos::logging::log;
 use leptos::prelude::*;
 use wasm_bindgen::JsCast;
 use web_sys::MouseEvent;
-use web_sys::js_sys;
 
-use crate::types::pixel_canvas::PixelCanvas;
+use crate::components::color_picker::ColorPicker;
+use crate::components::export::ExportMenu;
+use crate::components::import::ImportMenu;
+use crate::types::{color::RecentColors, pixel_canvas::PixelCanvas, shapes::Shape};
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum DrawingTool {
     Pen(PixelColor),
     Eraser,
     BucketFill(PixelColor),
+    Line(PixelColor),
+    Rectangle(PixelColor),
+    FilledRectangle(PixelColor),
+    Ellipse(PixelColor),
+    Select,
+    /// Pick a color off the canvas,then go back to the previous tool
+    Eyedropper,
 }
-fn export_image(canvas: Signal<PixelCanvas>) {
-    let bytes = canvas.get().image_export().unwrap();
-    let array = js_sys::Uint8Array::from(bytes.as_slice());
-
-    let blob = web_sys::Blob::new_with_u8_array_sequence(&js_sys::Array::of1(&array)).unwrap();
-
-    let url = web_sys::Url::create_object_url_with_blob(&blob).unwrap();
-
-    let document = web_sys::window().unwrap().document().unwrap();
-    let a = document.create_element("a").unwrap();
-    let a = a.dyn_into::<web_sys::HtmlAnchorElement>().unwrap();
-    a.set_href(&url);
-    a.set_download("pixel_art.png");
-    a.click();
-
-    web_sys::Url::revoke_object_url(&url).unwrap();
-}
-
 impl Default for DrawingTool {
     fn default() -> Self {
         DrawingTool::Pen(PixelColor::BLACK)
@@ -45,6 +37,12 @@ impl DrawingTool {
             DrawingTool::Pen(_) => "✏️",
             DrawingTool::Eraser => "🧽",
             DrawingTool::BucketFill(_) => "🪣",
+            DrawingTool::Line(_) => "📏",
+            DrawingTool::Rectangle(_) => "▭",
+            DrawingTool::FilledRectangle(_) => "■",
+            DrawingTool::Ellipse(_) => "◯",
+            DrawingTool::Select => "⬚",
+            DrawingTool::Eyedropper => "💧",
         }
     }
 
@@ -53,6 +51,12 @@ impl DrawingTool {
             DrawingTool::Pen(_) => "Pen",
             DrawingTool::Eraser => "Eraser",
             DrawingTool::BucketFill(_) => "Bucket Fill",
+            DrawingTool::Line(_) => "Line",
+            DrawingTool::Rectangle(_) => "Rect",
+            DrawingTool::FilledRectangle(_) => "Fill Rect",
+            DrawingTool::Ellipse(_) => "Ellipse",
+            DrawingTool::Select => "Select",
+            DrawingTool::Eyedropper => "Eyedropper",
         }
     }
     pub fn change_color(&mut self, color: PixelColor) {
@@ -60,19 +64,44 @@ impl DrawingTool {
             DrawingTool::Eraser => DrawingTool::Eraser,
             DrawingTool::Pen(_) => DrawingTool::Pen(color),
             DrawingTool::BucketFill(_) => DrawingTool::BucketFill(color),
+            DrawingTool::Line(_) => DrawingTool::Line(color),
+            DrawingTool::Rectangle(_) => DrawingTool::Rectangle(color),
+            DrawingTool::FilledRectangle(_) => DrawingTool::FilledRectangle(color),
+            DrawingTool::Ellipse(_) => DrawingTool::Ellipse(color),
+            DrawingTool::Select => DrawingTool::Select,
+            DrawingTool::Eyedropper => DrawingTool::Eyedropper,
+        }
+    }
+    /// Color the tool draws with,None for tools without a color
+    pub fn color(&self) -> Option<PixelColor> {
+        match *self {
+            DrawingTool::Pen(color)
+            | DrawingTool::BucketFill(color)
+            | DrawingTool::Line(color)
+            | DrawingTool::Rectangle(color)
+            | DrawingTool::FilledRectangle(color)
+            | DrawingTool::Ellipse(color) => Some(color),
+            DrawingTool::Eraser | DrawingTool::Select | DrawingTool::Eyedropper => None,
+        }
+    }
+    /// Shape drawn by dragging with this tool,and its color
+    pub fn shape(&self) -> Option<(Shape, PixelColor)> {
+        match *self {
+            DrawingTool::Line(color) => Some((Shape::Line, color)),
+            DrawingTool::Rectangle(color) => Some((Shape::Rectangle, color)),
+            DrawingTool::FilledRectangle(color) => Some((Shape::FilledRectangle, color)),
+            DrawingTool::Ellipse(color) => Some((Shape::Ellipse, color)),
+            _ => None,
         }
     }
 }
 
-const COLORS: [(&str, PixelColor); 8] = [
-    ("#000000", PixelColor::BLACK),
-    ("#ff0000", PixelColor::RED),
-    ("#00ff00", PixelColor::GREEN),
-    ("#0000ff", PixelColor::BLUE),
-    ("#ffff00", PixelColor::YELLOW),
-    ("#ff00ff", PixelColor::MAGENTA),
-    ("#00ffff", PixelColor::CYAN),
-    ("#ffffff", PixelColor::WHITE),
+/// Shape tools,shown after the basic tools in the toolbar
+const SHAPE_TOOLS: [DrawingTool; 4] = [
+    DrawingTool::Line(PixelColor::BLACK),
+    DrawingTool::Rectangle(PixelColor::BLACK),
+    DrawingTool::FilledRectangle(PixelColor::BLACK),
+    DrawingTool::Ellipse(PixelColor::BLACK),
 ];
 
 use base64::Engine as _;
@@ -81,9 +110,14 @@ use leptos::*; // for base64 encoding
 #[component]
 pub fn Toolbar(
     #[prop(into)] selected_tool: RwSignal<DrawingTool>,
-    #[prop(into)] canvas: Signal<PixelCanvas>,
+    #[prop(into)] canvas: RwSignal<PixelCanvas>,
+    recent_colors: RwSignal<RecentColors>,
+    #[prop(into)] palette: Signal<Palette>,
+    #[prop(into)] on_palette_change: Callback<Palette>,
 ) -> impl IntoView {
     let show_color_picker = RwSignal::new(false);
+    let show_export = RwSignal::new(false);
+    let show_import = RwSignal::new(false);
     let color_picker_position = RwSignal::new((0f64, 0f64)); // (left, top) in pixels
     //
 
@@ -115,15 +149,19 @@ pub fn Toolbar(
 
             if let Some(toolbar_rect) = toolbar_rect {
                 // Calculate position relative to toolbar
-                let left = rect.left() - toolbar_rect.left() + (rect.width() / 2.0) - 75.0;
-                // Center under button, 60px is half of color picker width
+                let left = rect.left() - toolbar_rect.left() + (rect.width() / 2.0) - 110.0;
+                // Center under button, 110px is half of color picker width
                 let top = rect.bottom() - toolbar_rect.top() + 5.0; // 5px gap below button
 
                 color_picker_position.set((left, top));
             }
         }
 
-        // Set the tool and show color picker
+        // Set the tool,keeping the current color,and show color picker
+        let mut tool = tool;
+        if let Some(color) = selected_tool.get_untracked().color() {
+            tool.change_color(color);
+        }
         selected_tool.set(tool);
         show_color_picker.set(!show_color_picker.get());
     };
@@ -189,10 +227,106 @@ pub fn Toolbar(
                     <span class="tool-icon">"🧽"</span>
                     <span class="tool-name">"Eraser"</span>
                 </button>
+
+                // Select button
+                <button
+                    class=move || {
+                        if matches!(selected_tool.get(), DrawingTool::Select) {
+                            "tool-button active"
+                        } else {
+                            "tool-button"
+                        }
+                    }
+                    title="Drag to select,drag inside to move (Ctrl to duplicate),Ctrl+C/X/V to copy,cut and paste"
+                    on:click=move |_| {
+                        selected_tool.set(DrawingTool::Select);
+                    }
+                >
+                    <span class="tool-icon">"⬚"</span>
+                    <span class="tool-name">"Select"</span>
+                </button>
+
+                // Eyedropper button
+                <button
+                    class=move || {
+                        if matches!(selected_tool.get(), DrawingTool::Eyedropper) {
+                            "tool-button active"
+                        } else {
+                            "tool-button"
+                        }
+                    }
+                    title="Pick a color from the canvas (I),or Alt+click with any tool"
+                    on:click=move |_| {
+                        selected_tool.set(DrawingTool::Eyedropper);
+                    }
+                >
+                    <span class="tool-icon">"💧"</span>
+                    <span class="tool-name">"Picker"</span>
+                </button>
+
+                // Shape buttons,right click to pick the color
+                {SHAPE_TOOLS.iter().map(|tool| {
+                    let tool = tool.clone();
+                    let tool_c = tool.clone();
+                    let tool_cc = tool.clone();
+                    let is_active = move || {
+                        std::mem::discriminant(&selected_tool.get()) == std::mem::discriminant(&tool_c)
+                    };
+                    view! {
+                        <button
+                            class=move || if is_active() { "tool-button active" } else { "tool-button" }
+                            on:click={
+                                let tool = tool.clone();
+                                move |_| selected_tool.set(tool.clone())
+                            }
+                            on:contextmenu=move |ev: MouseEvent| {
+                                handle_color_picker(ev, tool_cc.clone());
+                            }
+                        >
+                            <span class="tool-icon">{tool.icon()}</span>
+                            <span class="tool-name">{tool.name()}</span>
+                        </button>
+                    }
+                }).collect::<Vec<_>>()}
                 <button
-                    class= "tool-button"
+                    class="tool-button"
+                    title="Undo (Ctrl+Z / Shift+U)"
+                    disabled=move || !canvas.with(|pc| pc.can_undo())
                     on:click=move |_| {
-                        export_image(canvas)
+                        canvas.update(|pc| pc.undo());
+                    }
+                >
+                    <span class="tool-icon">"↩️"</span>
+                    <span class="tool-name">"Undo"</span>
+                </button>
+                <button
+                    class="tool-button"
+                    title="Redo (Ctrl+Shift+Z / Shift+R)"
+                    disabled=move || !canvas.with(|pc| pc.can_redo())
+                    on:click=move |_| {
+                        canvas.update(|pc| pc.redo());
+                    }
+                >
+                    <span class="tool-icon">"↪️"</span>
+                    <span class="tool-name">"Redo"</span>
+                </button>
+                <button
+                    class=move || if show_import.get() { "tool-button active" } else { "tool-button" }
+                    title="Load a png or jpeg onto the active layer"
+                    on:click=move |_| {
+                        show_export.set(false);
+                        show_import.update(|show| *show = !*show);
+                    }
+                >
+                    <span class="tool-icon">"📂"</span>
+                    <span class="tool-name">"Import"</span>
+                </button>
+                <button
+                    class=move || if show_export.get() { "tool-button active" } else { "tool-button" }
+                    title="Download the visible layers as a png,gif or spritesheet"
+                    on:click=move |_| {
+                        show_import.set(false);
+                        show_export.update(|show| *show = !*show);
                     }
                 >
                     <span class="tool-icon">"📥"</span>
@@ -201,6 +335,13 @@ pub fn Toolbar(
 
             </div>
 
+            <Show when=move || show_export.get()>
+                <ExportMenu canvas=canvas/>
+            </Show>
+            <Show when=move || show_import.get()>
+                <ImportMenu canvas=canvas palette=palette/>
+            </Show>
+
             // Color picker (dynamically positioned)
             <div
                 class=move || if show_color_picker.get() { "color-picker show" } else { "color-picker" }
@@ -211,22 +352,13 @@ pub fn Toolbar(
                     )
                 }
             >
-                <div class="color-grid">
-                    {COLORS.iter().map(|(hex, pixel_color)| {
-                        let color = *pixel_color;
-                        let hex_str = hex.to_string();
-                        view! {
-                            <button
-                                class="color-button"
-                                style=move || format!("background-color: {hex_str}")
-                                on:click=move |_| {
-                                    selected_tool.update(|x| x.change_color(color));
-                                    show_color_picker.set(false);
-                                }
-                            />
-                        }
-                    }).collect::<Vec<_>>()}
-                </div>
+                <ColorPicker
+                    color=Signal::derive(move || selected_tool.get().color().unwrap_or(PixelColor::BLACK))
+                    on_pick=move |color| selected_tool.update(|x| x.change_color(color))
+                    recent_colors=recent_colors
+                    palette=palette
+                    on_palette_change=on_palette_change
+                />
             </div>
         </div>
     }
@@ -235,7 +367,10 @@ pub fn Toolbar(
 #[component]
 pub fn ToolbarWithTrigger(
     #[prop(into)] selected_tool: RwSignal<DrawingTool>,
-    #[prop(into)] canvas: Signal<PixelCanvas>,
+    #[prop(into)] canvas: RwSignal<PixelCanvas>,
+    recent_colors: RwSignal<RecentColors>,
+    #[prop(into)] palette: Signal<Palette>,
+    #[prop(into)] on_palette_change: Callback<Palette>,
 ) -> impl IntoView {
     let show_toolbar = RwSignal::new(true);
     let first_time = RwSignal::new(false);
@@ -255,7 +390,13 @@ pub fn ToolbarWithTrigger(
             on:mouseenter=move |_| show_toolbar.set(true)
             on:mouseleave=move |_| show_toolbar.set(false)
         >
-            <Toolbar selected_tool=selected_tool canvas=canvas/>
+            <Toolbar
+                selected_tool=selected_tool
+                canvas=canvas
+                recent_colors=recent_colors
+                palette=palette
+                on_palette_change=on_palette_change
+            />
         </div>
     }
 }
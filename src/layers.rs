@@ -0,0 +1,370 @@
+//! Layers of a drawing board,shared by the drawing page and the server.
+//!
+//! Layers are stacked bottom to top and composited with their opacity.
+//! The structure is changed through [`LayerOp`],so every client of a session applies the same changes.
+use serde::{Deserialize, Serialize};
+use shared::{DrawingPixelCanvas, PixelColor};
+
+/// A session can't have more layers than this
+pub const MAX_LAYERS: usize = 32;
+/// Longest layer name,in characters
+pub const MAX_LAYER_NAME: usize = 64;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LayerInfo {
+    /// Stable id,the index of a layer changes when it is moved
+    pub id: u32,
+    pub name: String,
+    pub visible: bool,
+    /// Locked layers can't be drawn on
+    pub locked: bool,
+    /// 0 is fully transparent,255 fully opaque
+    pub opacity: u8,
+}
+
+impl LayerInfo {
+    pub fn new(id: u32, name: impl Into<String>) -> Self {
+        Self {
+            id,
+            name: name.into(),
+            visible: true,
+            locked: false,
+            opacity: 255,
+        }
+    }
+    /// Visible and unlocked
+    pub fn is_editable(&self) -> bool {
+        self.visible && !self.locked
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Layer {
+    pub info: LayerInfo,
+    pub canvas: DrawingPixelCanvas,
+}
+
+/// Change of the layer structure,applied the same way by the server and every client
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum LayerOp {
+    /// New empty layer on top,the id is given by the server
+    Add { id: u32, name: String },
+    Remove { id: u32 },
+    Rename { id: u32, name: String },
+    /// Move a layer to `index`,0 is the bottom
+    Move { id: u32, index: usize },
+    SetVisible { id: u32, visible: bool },
+    SetLocked { id: u32, locked: bool },
+    SetOpacity { id: u32, opacity: u8 },
+}
+
+/// Layers of a board,bottom first.
+/// There is always at least one layer.
+#[derive(Clone, Debug)]
+pub struct LayerStack {
+    width: usize,
+    height: usize,
+    layers: Vec<Layer>,
+    /// Id of the next added layer,ids are never reused
+    next_id: u32,
+}
+
+impl LayerStack {
+    /// Board with a single empty background layer
+    pub fn new(width: usize, height: usize) -> Self {
+        Self::from_canvas(DrawingPixelCanvas::new(width, height))
+    }
+    /// Board with a single background layer holding the canvas
+    pub fn from_canvas(canvas: DrawingPixelCanvas) -> Self {
+        let (width, height) = canvas.dimension();
+        Self {
+            width,
+            height,
+            layers: vec![Layer {
+                info: LayerInfo::new(0, "Background"),
+                canvas,
+            }],
+            next_id: 1,
+        }
+    }
+    /// Rebuild a board,the layers must all have the given dimension
+    pub fn from_layers(
+        width: usize,
+        height: usize,
+        layers: Vec<Layer>,
+        next_id: u32,
+    ) -> Result<Self, String> {
+        if layers.is_empty() {
+            return Err("a board needs at least one layer".to_string());
+        }
+        if let Some(layer) = layers.iter().find(|layer| layer.canvas.dimension() != (width, height)) {
+            return Err(format!("layer {} isn't {width}x{height}", layer.info.name));
+        }
+        let next_id = layers
+            .iter()
+            .map(|layer| layer.info.id + 1)
+            .max()
+            .unwrap_or(0)
+            .max(next_id);
+        Ok(Self {
+            width,
+            height,
+            layers,
+            next_id,
+        })
+    }
+
+    /// Same layers with empty pixels,used for a new animation frame
+    pub fn empty_copy(&self) -> Self {
+        Self {
+            layers: self
+                .layers
+                .iter()
+                .map(|layer| Layer {
+                    info: layer.info.clone(),
+                    canvas: DrawingPixelCanvas::new(self.width, self.height),
+                })
+                .collect(),
+            ..self.clone()
+        }
+    }
+
+    pub fn dimension(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+    /// Layers,bottom first
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+    pub fn next_id(&self) -> u32 {
+        self.next_id
+    }
+    pub fn get(&self, id: u32) -> Option<&Layer> {
+        self.layers.iter().find(|layer| layer.info.id == id)
+    }
+    pub fn get_mut(&mut self, id: u32) -> Option<&mut Layer> {
+        self.layers.iter_mut().find(|layer| layer.info.id == id)
+    }
+    pub fn index_of(&self, id: u32) -> Option<usize> {
+        self.layers.iter().position(|layer| layer.info.id == id)
+    }
+    pub fn bottom_mut(&mut self) -> &mut Layer {
+        &mut self.layers[0]
+    }
+    pub fn top(&self) -> &Layer {
+        self.layers.last().expect("a board always has a layer")
+    }
+
+    /// Give an id to a new layer before the op is applied and shared
+    pub fn assign_id(&self, op: &mut LayerOp) {
+        if let LayerOp::Add { id, .. } = op {
+            *id = self.next_id;
+        }
+    }
+
+    /// Check if the op can be applied,without changing anything
+    pub fn check(&self, op: &LayerOp) -> Result<(), String> {
+        match op {
+            LayerOp::Add { id, .. } => {
+                if self.layers.len() >= MAX_LAYERS {
+                    return Err(format!("at most {MAX_LAYERS} layers are supported"));
+                }
+                if self.get(*id).is_some() {
+                    return Err(format!("layer {id} already exists"));
+                }
+            }
+            LayerOp::Remove { id } => {
+                if self.layers.len() == 1 {
+                    return Err("the last layer can't be removed".to_string());
+                }
+                self.index_of(*id).ok_or(format!("no layer {id}"))?;
+            }
+            LayerOp::Move { id, .. }
+            | LayerOp::Rename { id, .. }
+            | LayerOp::SetVisible { id, .. }
+            | LayerOp::SetLocked { id, .. }
+            | LayerOp::SetOpacity { id, .. } => {
+                self.index_of(*id).ok_or(format!("no layer {id}"))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply the op,nothing changes if it fails
+    pub fn apply(&mut self, op: &LayerOp) -> Result<(), String> {
+        self.check(op)?;
+        match op {
+            LayerOp::Add { id, name } => {
+                self.layers.push(Layer {
+                    info: LayerInfo::new(*id, clean_name(name)),
+                    canvas: DrawingPixelCanvas::new(self.width, self.height),
+                });
+                self.next_id = self.next_id.max(id + 1);
+            }
+            LayerOp::Remove { id } => {
+                let index = self.index_of(*id).ok_or(format!("no layer {id}"))?;
+                self.layers.remove(index);
+            }
+            LayerOp::Move { id, index } => {
+                let from = self.index_of(*id).ok_or(format!("no layer {id}"))?;
+                let layer = self.layers.remove(from);
+                let index = (*index).min(self.layers.len());
+                self.layers.insert(index, layer);
+            }
+            LayerOp::Rename { id, name } => self.info_mut(*id)?.name = clean_name(name),
+            LayerOp::SetVisible { id, visible } => self.info_mut(*id)?.visible = *visible,
+            LayerOp::SetLocked { id, locked } => self.info_mut(*id)?.locked = *locked,
+            LayerOp::SetOpacity { id, opacity } => self.info_mut(*id)?.opacity = *opacity,
+        }
+        Ok(())
+    }
+
+    fn info_mut(&mut self, id: u32) -> Result<&mut LayerInfo, String> {
+        self.get_mut(id)
+            .map(|layer| &mut layer.info)
+            .ok_or(format!("no layer {id}"))
+    }
+
+    /// Composite the visible layers into one canvas
+    pub fn flatten(&self) -> DrawingPixelCanvas {
+        let mut flattened = DrawingPixelCanvas::new(self.width, self.height);
+        for layer in self.layers.iter().filter(|layer| layer.info.visible) {
+            blend_onto(&mut flattened, &layer.canvas, layer.info.opacity);
+        }
+        flattened
+    }
+
+    /// Erase the pixels of every layer
+    pub fn clear(&mut self) {
+        for layer in &mut self.layers {
+            layer.canvas.clear();
+        }
+    }
+    /// Change the size of every layer,the pixel at (x,y) moves to (x+dx,y+dy).
+    /// Pixels moved outside the new size are lost.
+    pub fn resize(&mut self, width: usize, height: usize, (dx, dy): (isize, isize)) {
+        for layer in &mut self.layers {
+            let mut canvas = DrawingPixelCanvas::new(width, height);
+            for y in 0..height {
+                let Some(from_y) = y.checked_add_signed(-dy).filter(|y| *y < self.height) else {
+                    continue;
+                };
+                for x in 0..width {
+                    let Some(from_x) = x.checked_add_signed(-dx).filter(|x| *x < self.width) else {
+                        continue;
+                    };
+                    canvas.draw_pixel_ignore(x, y, layer.canvas.get_pixel(from_x, from_y));
+                }
+            }
+            layer.canvas = canvas;
+        }
+        self.width = width;
+        self.height = height;
+    }
+}
+
+fn clean_name(name: &str) -> String {
+    let name: String = name.trim().chars().take(MAX_LAYER_NAME).collect();
+    if name.is_empty() { "Layer".to_string() } else { name }
+}
+
+/// Draw a layer over a canvas with the given opacity,translucent pixels are alpha blended
+pub fn blend_onto(target: &mut DrawingPixelCanvas, layer: &DrawingPixelCanvas, opacity: u8) {
+    let (width, height) = target.dimension();
+    for y in 0..height {
+        for x in 0..width {
+            let color = layer.get_pixel(x, y);
+            if color == PixelColor::TRANSPARENT || color == PixelColor::ERASE {
+                continue;
+            }
+            let [r, g, b, a] = color.to_rgba();
+            let alpha = (a as u32 * opacity as u32 / 255) as u8;
+            if alpha == 0 {
+                continue;
+            }
+            let below = target.get_pixel(x, y);
+            let blended = if alpha == 255 || below == PixelColor::TRANSPARENT {
+                [r, g, b, alpha]
+            } else {
+                over([r, g, b, alpha], below.to_rgba())
+            };
+            let [r, g, b, a] = blended;
+            target.draw_pixel_ignore(x, y, PixelColor::new(r, g, b, a));
+        }
+    }
+}
+
+/// Porter-Duff "over",`top` drawn above `bottom`
+fn over(top: [u8; 4], bottom: [u8; 4]) -> [u8; 4] {
+    let top_alpha = top[3] as f32 / 255.0;
+    let bottom_alpha = bottom[3] as f32 / 255.0 * (1.0 - top_alpha);
+    let alpha = top_alpha + bottom_alpha;
+    let channel = |i: usize| ((top[i] as f32 * top_alpha + bottom[i] as f32 * bottom_alpha) / alpha).round() as u8;
+    [channel(0), channel(1), channel(2), (alpha * 255.0).round() as u8]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Positions of the pixels that aren't transparent on the bottom layer
+    fn drawn(stack: &LayerStack) -> Vec<(usize, usize)> {
+        let (width, height) = stack.dimension();
+        let canvas = &stack.layers()[0].canvas;
+        (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .filter(|&(x, y)| canvas.get_pixel(x, y) != PixelColor::TRANSPARENT)
+            .collect()
+    }
+
+    #[test]
+    fn resize_grows_around_the_offset() {
+        let mut stack = LayerStack::new(2, 2);
+        stack
+            .bottom_mut()
+            .canvas
+            .draw_pixel_ignore(1, 1, PixelColor::RED);
+        stack.resize(4, 4, (1, 1));
+
+        assert_eq!(stack.dimension(), (4, 4));
+        assert_eq!(stack.layers()[0].canvas.dimension(), (4, 4));
+        assert_eq!(stack.layers()[0].canvas.get_pixel(2, 2), PixelColor::RED);
+        assert_eq!(drawn(&stack), vec![(2, 2)]);
+    }
+
+    #[test]
+    fn resize_shrinks_and_drops_the_pixels_cut_off() {
+        let mut stack = LayerStack::new(4, 4);
+        stack
+            .bottom_mut()
+            .canvas
+            .draw_pixel_ignore(0, 0, PixelColor::RED);
+        stack
+            .bottom_mut()
+            .canvas
+            .draw_pixel_ignore(3, 2, PixelColor::RED);
+        stack.resize(2, 2, (-2, -1));
+
+        assert_eq!(stack.dimension(), (2, 2));
+        assert_eq!(drawn(&stack), vec![(1, 1)]);
+    }
+
+    #[test]
+    fn resize_changes_every_layer() {
+        let mut stack = LayerStack::new(3, 3);
+        let mut add = LayerOp::Add {
+            id: 0,
+            name: "Ink".to_string(),
+        };
+        stack.assign_id(&mut add);
+        stack.apply(&add).unwrap();
+        stack.resize(5, 1, (1, 0));
+
+        assert!(
+            stack
+                .layers()
+                .iter()
+                .all(|layer| layer.canvas.dimension() == (5, 1))
+        );
+    }
+}
@@ -1,2 +1,7 @@
 pub use shared as prelude;
 
+pub mod board;
+pub mod layers;
+pub mod palette;
+pub mod protocol;
+pub mod snapshot;
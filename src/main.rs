@@ -1,7 +1,13 @@
 use std::time::Duration;
 
-use bincode::config;
+use frontend::board::{FrameOp, MAX_BOARD_SIDE};
+use frontend::layers::LayerOp;
+use frontend::palette::Palette;
 use frontend::prelude::BytesPassthrough;
+use frontend::protocol::{
+    ChatMessage, ClientMessage, KEY_QUERY, MAX_CHAT_HISTORY, OwnerAction, Presence, Role,
+    ServerMessage, SessionKeys, clean_name,
+};
 // This can be empty for now or contain server-side code if you plan to use SSR later
 use leptos::{
     logging::{error, log},
@@ -12,17 +18,35 @@ use leptos_router::{
     hooks::{use_params_map, use_query_map},
     path,
 };
-use leptos_use::{self, UseWebSocketReturn, core::ConnectionReadyState, use_websocket};
+use leptos_use::{
+    self, ReconnectLimit, UseWebSocketOptions, UseWebSocketReturn, core::ConnectionReadyState,
+    use_websocket_with_options,
+};
 mod components;
+mod services;
 mod types;
-use components::{Canvas, DrawingTool};
+use components::{
+    Canvas, ChatPanel, ConnectionStatus, DrawingTool, Gallery, LayersPanel, OwnerControls,
+    ParticipantsPanel, StatusBadge, Timeline,
+};
 pub use frontend::prelude;
 use shared::DataPass;
 use uuid::Uuid;
 
 use crate::{
     components::toolbar::ToolbarWithTrigger,
-    types::pixel_canvas::{GridIndex, PixelCanvas},
+    services::{
+        links,
+        storage::{self, DISPLAY_NAME_KEY, owner_secret_key},
+        websocket::{ENDPOINT_QUERY, websocket_url},
+    },
+    types::{
+        color::RecentColors,
+        pixel_canvas::{GridIndex, PixelCanvas},
+        presence::Participants,
+        recent::{DEFAULT_SESSION_NAME, RecentSession, RecentSessions},
+        sync::SyncState,
+    },
 };
 
 #[component]
@@ -85,14 +109,34 @@ fn HomePage() -> impl IntoView {
                     "🚀 Start Drawing"
                 </A>
             </div>
+
+            <Gallery/>
         </div>
     }
 }
 
+/// Delay before the first reconnect attempt,doubled after every failure
+const RECONNECT_DELAY_MIN: Duration = Duration::from_secs(1);
+const RECONNECT_DELAY_MAX: Duration = Duration::from_secs(30);
+/// How often the hovered cell and tool are sent,when they changed
+const PRESENCE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Display name remembered by the browser,a random guest name on the first visit
+fn stored_display_name() -> String {
+    storage::get(DISPLAY_NAME_KEY)
+        .and_then(|name| clean_name(&name))
+        .unwrap_or_else(|| format!("Guest {}", &Uuid::new_v4().simple().to_string()[..4]))
+}
+
 #[component]
 fn DrawingPage() -> impl IntoView {
     let params = use_params_map();
     let query = use_query_map();
+    let session_id = params
+        .with(|p| p.get("id"))
+        .unwrap_or_else(|| "unknown".to_string());
+    let ws_url = websocket_url(&session_id, query.with(|q| q.get(ENDPOINT_QUERY)));
+    log!("Connecting to {ws_url}");
     let UseWebSocketReturn {
         ready_state,
         message,
@@ -101,57 +145,96 @@ fn DrawingPage() -> impl IntoView {
         close,
         send,
         ..
-    } = use_websocket::<Vec<u8>, Vec<u8>, BytesPassthrough>("ws://127.0.0.1:8081");
+    } = use_websocket_with_options::<Vec<u8>, Vec<u8>, BytesPassthrough, _, _>(
+        &ws_url,
+        // Reconnecting is handled below,with an exponential backoff
+        UseWebSocketOptions::default().reconnect_limit(ReconnectLimit::Limited(0)),
+    );
 
-    let session_id = params
-        .with(|p| p.get("id"))
-        .unwrap_or_else(|| "unknown".to_string());
-    let width: usize = query.with(|q| q.get("width").and_then(|v| v.parse().ok()).unwrap_or(100));
-    let height: usize = query.with(|q| q.get("height").and_then(|v| v.parse().ok()).unwrap_or(100));
-    let data = DataPass::Whid {
-        width,
-        height,
-        id: session_id.clone(),
+    // Capability key of the link,a page without it views the board unless it creates it
+    let key = query.with(|q| q.get(KEY_QUERY));
+    // The server refuses other sizes,a hand-edited link still gets a board
+    let side = |name: &str| -> usize {
+        query
+            .with(|q| q.get(name).and_then(|v| v.parse().ok()))
+            .unwrap_or(100)
+            .clamp(1, MAX_BOARD_SIDE)
     };
-    let buf = bincode::serde::encode_to_vec(&data, bincode::config::standard()).unwrap();
-    let sent = RwSignal::new(false);
-    let send_c = send.clone();
-    Effect::new(move |_| {
-        if !sent.get() && ready_state.get() == ConnectionReadyState::Open {
-            match bincode::serde::encode_to_vec(&data, bincode::config::standard()) {
-                Ok(buf) => {
-                    log!("Connection established! Sending data:");
-                    log!("width: {width}, height: {height}");
-                    log!("encoded data: {buf:?}");
-                    send_c(&buf);
-                    sent.set(true); // Mark as sent
-                }
-                Err(e) => {
-                    log!("Failed to encode data: {e:?}");
-                }
+    let (width, height) = (side("width"), side("height"));
+
+    //region reconnect
+    // Number of failed attempts since the connection was lost
+    let reconnect_attempts = RwSignal::new(0u32);
+    let connected_once = RwSignal::new(false);
+    // Removed by the owner,the server closed the connection for good
+    let kicked = RwSignal::new(false);
+    Effect::new(move |previous: Option<ConnectionReadyState>| {
+        let state = ready_state.get();
+        match state {
+            ConnectionReadyState::Open => {
+                log!("Connection established!");
+                connected_once.set(true);
+                reconnect_attempts.set(0);
+            }
+            ConnectionReadyState::Closed if kicked.get_untracked() => {
+                log!("Removed from the session by its owner,not reconnecting");
+            }
+            // Only react when the socket just closed,not to the initial state
+            ConnectionReadyState::Closed
+                if previous.is_some_and(|previous| previous != ConnectionReadyState::Closed) =>
+            {
+                let attempts = reconnect_attempts.get_untracked();
+                let delay = RECONNECT_DELAY_MIN
+                    .saturating_mul(2u32.saturating_pow(attempts))
+                    .min(RECONNECT_DELAY_MAX);
+                log!("Connection lost,reconnecting in {delay:?}");
+                reconnect_attempts.set(attempts + 1);
+                let open = open.clone();
+                set_timeout(move || open(), delay);
             }
+            _ => {}
         }
+        state
     });
-    log!(
-        "
-        now width is {width},
-        height is {height},
-        new send from {buf:?}"
-    );
+    let status = Signal::derive(move || match ready_state.get() {
+        _ if kicked.get() => ConnectionStatus::Removed,
+        ConnectionReadyState::Open => ConnectionStatus::Connected,
+        _ if !connected_once.get() && reconnect_attempts.get() == 0 => {
+            ConnectionStatus::Connecting
+        }
+        ConnectionReadyState::Connecting => ConnectionStatus::Reconnecting,
+        _ => ConnectionStatus::Disconnected,
+    });
+    //endregion
+
     view! {
         <div>
             <p>{format!("Session: {session_id}")}</p>
-            <App width=width height=height message=message send=send ready_state=ready_state/>
+            <StatusBadge status=status/>
+            <App
+                session_id=session_id.clone()
+                key=key
+                width=width
+                height=height
+                message=message
+                send=send
+                ready_state=ready_state
+                kicked=kicked
+            />
         </div>
     }
 }
 #[component]
 fn App(
+    session_id: String,
+    key: Option<String>,
     width: usize,
     height: usize,
     message: Signal<Option<Vec<u8>>>,
     send: impl Fn(&Vec<u8>) + Clone + Send + Sync + 'static,
     ready_state: Signal<ConnectionReadyState>,
+    /// Set when the owner removes this user
+    kicked: RwSignal<bool>,
 ) -> impl IntoView {
     // Shared state for the selected drawing tool
     let send_c = send.clone();
@@ -161,28 +244,181 @@ fn App(
         y: height,
     }));
 
+    let recent_colors = RwSignal::new(RecentColors::default());
+    // Palette shared by the session,the basic colors until someone changes it
+    let palette = RwSignal::new(Palette::default());
+
+    // Layer changes go through the server,which gives new layers their id.
+    // They are applied when the server sends them back,so a change it rejects never shows.
+    let activate_added_layer = StoredValue::new(false);
+    let send_layer = send.clone();
+    let on_layer_op = Callback::new(move |op: LayerOp| {
+        if let LayerOp::Add { .. } = op {
+            activate_added_layer.set_value(true);
+        }
+        send_layer(&ClientMessage::Layer(op).to_bytes());
+    });
+    // Frame changes are shared the same way
+    let activate_added_frame = StoredValue::new(false);
+    let send_frame = send.clone();
+    let on_frame_op = Callback::new(move |op: FrameOp| {
+        if let FrameOp::Add { .. } = op {
+            activate_added_frame.set_value(true);
+        }
+        send_frame(&ClientMessage::Frame(op).to_bytes());
+    });
+
+    // Deltas sent to the server,kept until they are acknowledged
+    let sync = StoredValue::new(SyncState::default());
+    // Deltas are only sent once the session is joined on the current connection
+    let joined = StoredValue::new(false);
+    // Palette changed while the session wasn't joined,sent on the next join.
+    // It replaces the palette of the session until the initial sync is over.
+    let unsent_palette = StoredValue::new(None::<Palette>);
+    let send_palette = send.clone();
+    let on_palette_change = Callback::new(move |new_palette: Palette| {
+        if joined.get_value() {
+            send_palette(&ClientMessage::Palette(new_palette.clone()).to_bytes());
+        } else {
+            unsent_palette.set_value(Some(new_palette.clone()));
+        }
+        palette.set(new_palette);
+    });
+    // The other users and their cursors
+    let participants = RwSignal::new(Participants::default());
+    let display_name = RwSignal::new(stored_display_name());
+    let chat = RwSignal::new(Vec::<ChatMessage>::new());
+    let can_chat = Signal::derive(move || ready_state.get() == ConnectionReadyState::Open);
+    let send_chat = send.clone();
+    let on_chat = Callback::new(move |text: String| {
+        send_chat(&ClientMessage::Chat(text).to_bytes());
+    });
+    let hovered_cell = RwSignal::new(None::<GridIndex>);
+    // Presence last sent on this connection,None before the first one
+    let sent_presence = StoredValue::new(None::<Presence>);
+
+    // What this user may do,given by the server on join.
+    // The key becomes the editor key once known,so a reconnect or a reload keeps editing.
+    let page_key = StoredValue::new(key);
+    // The browser creating the session keeps the owner secret,so it stays the owner
+    let secret_key = owner_secret_key(&session_id);
+    let owner_secret = StoredValue::new(storage::get(&secret_key));
+    let role = RwSignal::new(Role::Viewer);
+    let keys = RwSignal::new(None::<SessionKeys>);
+    let locked = RwSignal::new(false);
+    let published = RwSignal::new(None::<String>);
+    let read_only = Signal::derive(move || !role.get().can_edit(locked.get()));
+    let is_owner = Signal::derive(move || role.get() == Role::Owner);
+    let dimension = Memo::new(move |_| canvas_state.with(|x| x.grid_dimension()));
+    let selection_rect = Memo::new(move |_| {
+        canvas_state.with(|x| x.selection().map(|selection| selection.rect.clone()))
+    });
+    let send_owner = send.clone();
+    let on_owner_action = Callback::new(move |action: OwnerAction| {
+        send_owner(&ClientMessage::Owner(action).to_bytes());
+    });
+    let on_kick = Callback::new(move |client: u64| {
+        on_owner_action.run(OwnerAction::Kick { client });
+    });
+    let link_session_id = session_id.clone();
+    let panel_session_id = session_id.clone();
+
+    // Remember the session for the gallery of the home page,with the key giving this user's role
+    let visit_session_id = session_id.clone();
+    let remember_visit = move || {
+        let (width, height) = canvas_state.with_untracked(|x| x.grid_dimension());
+        RecentSessions::load().visit(RecentSession {
+            id: visit_session_id.clone(),
+            name: published
+                .get_untracked()
+                .unwrap_or_else(|| DEFAULT_SESSION_NAME.to_string()),
+            width,
+            height,
+            visited_at: web_sys::js_sys::Date::now() as u64,
+            key: page_key.get_value(),
+        });
+    };
+
+    // Editors and the owner share the editor link,its key replaces the one of the page
+    let adopt_keys = move |new_keys: Option<SessionKeys>| {
+        if let Some(editor_key) = new_keys.as_ref().map(|keys| keys.editor.clone()) {
+            if page_key.with_value(|key| key.as_ref() != Some(&editor_key)) {
+                let link = links::session_link(&link_session_id, &editor_key);
+                if let Err(err) = links::replace_url(&link) {
+                    error!("{err}");
+                }
+                page_key.set_value(Some(editor_key));
+            }
+        }
+        keys.set(new_keys);
+    };
+
+    //region join session
+    let send_join = send.clone();
+    Effect::new(move |_| {
+        if ready_state.get() != ConnectionReadyState::Open {
+            joined.set_value(false);
+            sent_presence.set_value(None);
+            // The server sends the participants again on join
+            participants.update(|participants| participants.clear());
+            return;
+        }
+        // (Re)join the session,the server answers with a fresh snapshot
+        let join = ClientMessage::Pass(DataPass::Whid {
+            width,
+            height,
+            id: session_id.clone(),
+        });
+        log!("Joining session {session_id} ({width}x{height})");
+        if let Some(secret) = owner_secret.get_value() {
+            send_join(&ClientMessage::OwnerSecret(secret).to_bytes());
+        }
+        if let Some(key) = page_key.get_value() {
+            send_join(&ClientMessage::Key(key).to_bytes());
+        }
+        send_join(&join.to_bytes());
+        send_join(&ClientMessage::Name(display_name.get_untracked()).to_bytes());
+        if let Some(local) = unsent_palette.get_value() {
+            send_join(&ClientMessage::Palette(local).to_bytes());
+        }
+        // Replay the drawing the server never acknowledged,including what was drawn offline
+        sync.with_value(|sync| {
+            for delta in sync.pending() {
+                send_join(&ClientMessage::Delta(delta.clone()).to_bytes());
+            }
+        });
+        joined.set_value(true);
+    });
+    // Remember the name and tell the session when it changes
+    let send_name = send.clone();
+    Effect::new(move |previous: Option<String>| {
+        let name = display_name.get();
+        if let Err(err) = storage::set(DISPLAY_NAME_KEY, &name) {
+            error!("{err}");
+        }
+        if previous.is_some_and(|previous| previous != name) && joined.get_value() {
+            send_name(&ClientMessage::Name(name.clone()).to_bytes());
+        }
+        name
+    });
+    //endregion
+
     //region outgoing call
-    //Effect::new(move || {
-    //    if ready_state.get() == ConnectionReadyState::Open {
-    //        let bytes = canvas_state.get().main_canvas_to_bytes();
-    //        let bytes = bincode::serde::encode_to_vec(
-    //            &DataPass::Canvas { data: bytes },
-    //            config::standard(),
-    //        )
-    //        .unwrap();
-    //        send_c(&bytes);
-    //    }
-    //});
-    let send_c=send.clone();
+    // Only the pixels drawn since the last tick are sent,idle clients send nothing
     set_interval(
         move || {
-            let bytes = canvas_state.get().drawing_canvas_to_bytes();
-            let bytes = bincode::serde::encode_to_vec(
-                &DataPass::Canvas { data: bytes },
-                config::standard(),
-            )
-            .unwrap();
-            send_c(&bytes);
+            let seq = sync.with_value(|sync| sync.next_seq());
+            let deltas = canvas_state.with_untracked(|x| x.drawing_deltas(seq));
+            if deltas.is_empty() {
+                return;
+            }
+            for delta in deltas {
+                // Offline drawing stays pending,and is replayed when the session is joined again
+                if joined.get_value() {
+                    send_c(&ClientMessage::Delta(delta.clone()).to_bytes());
+                }
+                sync.update_value(|sync| sync.push(delta));
+            }
 
             canvas_state.update(|x| {
                 x.update_drawing();
@@ -191,6 +427,25 @@ fn App(
         Duration::from_secs_f32(1.0 / 4.0),
     );
 
+    let send_presence = send.clone();
+    set_interval(
+        move || {
+            if !joined.get_value() {
+                return;
+            }
+            let presence = Presence {
+                cell: hovered_cell
+                    .get_untracked()
+                    .map(|cell| (cell.x as u32, cell.y as u32)),
+                tool: selected_tool.with_untracked(|tool| tool.name().to_string()),
+            };
+            if sent_presence.with_value(|sent| sent.as_ref() != Some(&presence)) {
+                send_presence(&ClientMessage::Presence(presence.clone()).to_bytes());
+                sent_presence.set_value(Some(presence));
+            }
+        },
+        PRESENCE_INTERVAL,
+    );
     //endregion
     // region canvas ingoing call
     Effect::new(move || {
@@ -198,18 +453,152 @@ fn App(
             log!("Signal is null");
             return;
         };
-        canvas_state.update(|x| {
-            if let Err(err) = x.assign_pixel_bytes(&bin_data) {
+        let server_message = match ServerMessage::from_bytes(&bin_data) {
+            Ok(server_message) => server_message,
+            Err(err) => {
                 error!("{err}");
+                return;
             }
-        });
+        };
+        match server_message {
+            ServerMessage::Snapshot(data) => {
+                canvas_state.update(|x| {
+                    let previous = x.grid_dimension();
+                    if let Err(err) = x.assign_pixel_bytes(&data) {
+                        error!("{err}");
+                    }
+                    if x.grid_dimension() != previous {
+                        // Resized or cropped,the server drops the deltas drawn on the old board
+                        sync.update_value(|sync| sync.drop_pending());
+                        return;
+                    }
+                    // Keep the local drawing the server hasn't merged yet on top of the snapshot
+                    sync.with_value(|sync| sync.pending().for_each(|delta| x.apply_delta(delta)));
+                });
+                // Joined,or the board was cleared or resized
+                remember_visit();
+            }
+            ServerMessage::Ack { seq } => sync.update_value(|sync| sync.ack(seq)),
+            ServerMessage::Delta(delta) => canvas_state.update(|x| x.apply_delta(&delta)),
+            ServerMessage::Palette(new_palette) => {
+                // The palette of the initial sync is older than the one changed offline
+                if unsent_palette.with_value(Option::is_none) {
+                    palette.set(new_palette);
+                }
+            }
+            ServerMessage::Layer(op) => canvas_state.update(|x| {
+                if let Err(err) = x.apply_layer_op(&op) {
+                    error!("{err}");
+                }
+                if let LayerOp::Add { id, .. } = op {
+                    if activate_added_layer.get_value() {
+                        activate_added_layer.set_value(false);
+                        x.set_active_layer(id);
+                    }
+                }
+            }),
+            ServerMessage::Frame(op) => canvas_state.update(|x| {
+                if let Err(err) = x.apply_frame_op(&op) {
+                    error!("{err}");
+                }
+                if let FrameOp::Add { id, .. } = op {
+                    if activate_added_frame.get_value() {
+                        activate_added_frame.set_value(false);
+                        x.set_active_frame(id);
+                    }
+                }
+            }),
+            ServerMessage::Welcome {
+                client,
+                role: new_role,
+                keys: new_keys,
+                owner_secret: new_secret,
+            } => {
+                participants.update(|x| x.set_me(client));
+                role.set(new_role);
+                if let Some(secret) = new_secret {
+                    if owner_secret.with_value(|stored| stored.as_ref() != Some(&secret)) {
+                        if let Err(err) = storage::set(&secret_key, &secret) {
+                            error!("{err}");
+                        }
+                        owner_secret.set_value(Some(secret));
+                    }
+                }
+                adopt_keys(new_keys);
+            }
+            ServerMessage::Joined { client, name } => participants.update(|x| x.join(client, name)),
+            ServerMessage::Presence { client, presence } => {
+                participants.update(|x| x.update_presence(client, presence))
+            }
+            ServerMessage::Left { client } => participants.update(|x| x.remove(client)),
+            ServerMessage::ChatHistory(messages) => chat.set(messages),
+            ServerMessage::Chat(message) => chat.update(|chat| {
+                chat.push(message);
+                if chat.len() > MAX_CHAT_HISTORY {
+                    chat.remove(0);
+                }
+            }),
+            ServerMessage::Locked(new_locked) => locked.set(new_locked),
+            ServerMessage::Published(name) => published.set(name),
+            ServerMessage::Kicked { client } => {
+                if participants.with_untracked(|x| x.me()) == Some(client) {
+                    kicked.set(true);
+                } else {
+                    participants.update(|x| x.remove(client));
+                }
+            }
+            ServerMessage::Keys(new_keys) => {
+                adopt_keys(Some(new_keys));
+                // The gallery opens the session with the new key
+                remember_visit();
+            }
+            // The palette changed offline was sent before the initial sync ended,it is the session's now
+            ServerMessage::Synced => unsent_palette.set_value(None),
+        }
     });
     // endregion
 
     view! {
         <div class="app">
-            <ToolbarWithTrigger selected_tool=selected_tool canvas=canvas_state/>
-            <Canvas selected_tool=selected_tool canvas_state=canvas_state/>
+            <Show when=move || !read_only.get()>
+                <ToolbarWithTrigger
+                    selected_tool=selected_tool
+                    canvas=canvas_state
+                    recent_colors=recent_colors
+                    palette=palette
+                    on_palette_change=on_palette_change
+                />
+                <LayersPanel canvas=canvas_state on_layer_op=on_layer_op/>
+            </Show>
+            <Timeline canvas=canvas_state on_frame_op=on_frame_op read_only=read_only/>
+            <ParticipantsPanel
+                participants=participants
+                display_name=display_name
+                session_id=panel_session_id
+                role=role
+                locked=locked
+                keys=keys
+                on_kick=on_kick
+            >
+                <Show when=move || is_owner.get()>
+                    <OwnerControls
+                        locked=locked
+                        dimension=dimension
+                        selection=selection_rect
+                        published=published
+                        on_action=on_owner_action
+                    />
+                </Show>
+            </ParticipantsPanel>
+            <ChatPanel messages=chat on_send=on_chat can_send=can_chat/>
+            <Canvas
+                selected_tool=selected_tool
+                canvas_state=canvas_state
+                recent_colors=recent_colors
+                hovered_cell=hovered_cell
+                participants=participants
+                read_only=read_only
+            />
         </div>
     }
 }
@@ -0,0 +1,60 @@
+//! Png images of the sessions,rendered on the server for embedding.
+use std::io::Cursor;
+
+use frontend::prelude::{DrawingPixelCanvas, PixelColor};
+use image::{ImageFormat, Rgba, RgbaImage};
+
+/// Largest accepted `scale`
+pub const MAX_SCALE: u32 = 32;
+/// Longest side of a rendered image,the scale is lowered to stay under it
+pub const MAX_SIDE: u32 = 4096;
+
+/// Scale that can be used for a canvas,between 1 and the asked scale
+pub fn clamp_scale(canvas: &DrawingPixelCanvas, scale: u32) -> u32 {
+    let (width, height) = canvas.dimension();
+    let longest = width.max(height).max(1) as u32;
+    scale.clamp(1, MAX_SCALE).min(MAX_SIDE / longest).max(1)
+}
+
+/// Color a cell is rendered with,empty cells are transparent
+fn cell_rgba(color: PixelColor) -> [u8; 4] {
+    if color == PixelColor::TRANSPARENT || color == PixelColor::ERASE {
+        [0, 0, 0, 0]
+    } else {
+        color.to_rgba()
+    }
+}
+
+/// ETag of the png of a canvas,a 64 bit FNV-1a hash of its size,scale and rendered cells.
+/// The same image always gets the same tag,across reloads of the session,restarts and builds.
+pub fn etag(canvas: &DrawingPixelCanvas, scale: u32) -> String {
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    let (width, height) = canvas.dimension();
+    let header = [width as u32, height as u32, clamp_scale(canvas, scale)]
+        .into_iter()
+        .flat_map(u32::to_le_bytes);
+    let cells = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .flat_map(|(x, y)| cell_rgba(canvas.get_pixel(x, y)));
+    let hash = header.chain(cells).fold(OFFSET, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(PRIME)
+    });
+    format!("\"{hash:016x}\"")
+}
+
+/// Png of a canvas,every cell is `scale`x`scale` pixels and empty cells are transparent
+pub fn png(canvas: &DrawingPixelCanvas, scale: u32) -> Result<Vec<u8>, String> {
+    let scale = clamp_scale(canvas, scale);
+    let (width, height) = canvas.dimension();
+    let image = RgbaImage::from_fn(width as u32 * scale, height as u32 * scale, |x, y| {
+        Rgba(cell_rgba(
+            canvas.get_pixel((x / scale) as usize, (y / scale) as usize),
+        ))
+    });
+    let mut bytes = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+        .map_err(|e| format!("Failed to encode png: {e}"))?;
+    Ok(bytes)
+}
@@ -1,15 +1,23 @@
 use axum::{
     extract::{
         ws::{Message, WebSocket, WebSocketUpgrade},
-        State,
+        Path, Query, State,
     },
-    response::{Html, IntoResponse},
-    routing::{get, get_service},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Json, Response},
+    routing::get,
     Router,
 };
 use futures_util::{sink::SinkExt, stream::StreamExt};
-use std::sync::{Arc, Mutex};
-use tokio::sync::broadcast;
+use serde::Deserialize;
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, SystemTime},
+};
+use tokio::sync::broadcast::{self, error::RecvError};
 use tower::ServiceBuilder;
 use tower_http::{
     cors::CorsLayer,
@@ -18,102 +26,469 @@ use tower_http::{
 use tracing::{info, warn, error};
 
 // Import our shared protocol
-use rust_web::{DrawEvent, CanvasState};
+use frontend::board::FrameOp;
+use frontend::layers::LayerOp;
+use frontend::palette::{MAX_PALETTE_COLORS, Palette};
+use frontend::prelude::{DataPass, DrawingPixelCanvas};
+use frontend::protocol::{
+    CanvasDelta, ChatMessage, ClientMessage, OwnerAction, Presence, Role, SESSIONS_PATH,
+    ServerMessage, SessionListing, clean_chat, clean_name,
+};
+use frontend::snapshot;
+
+mod render;
+mod session;
+use session::{Broadcast, DEFAULT_SESSION_DIR, Participant, Session, SessionStore, unix_millis};
+
+/// Output directory of `trunk build`
+const DIST_DIR: &str = "dist";
+/// How often changed sessions are written to disk
+const SAVE_INTERVAL: Duration = Duration::from_secs(5);
+/// Sessions without clients for this long are dropped from memory,they stay on disk
+const IDLE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
 
 /// Shared application state
 #[derive(Clone)]
 struct AppState {
-    /// Canvas state shared across all clients
-    canvas: Arc<Mutex<CanvasState>>,
-    /// Broadcast channel for real-time updates
-    tx: broadcast::Sender<Vec<u8>>,
+    /// Every drawing session,keyed by the uuid of the drawing page
+    sessions: Arc<Mutex<SessionStore>>,
+    /// Id given to the next connected client
+    next_client_id: Arc<AtomicUsize>,
+    /// Held while sessions are written,so two flushes never write the same files
+    saving: Arc<tokio::sync::Mutex<()>>,
 }
 
 impl AppState {
-    fn new() -> Self {
-        let (tx, _rx) = broadcast::channel(1000);
+    fn new(store: SessionStore) -> Self {
         Self {
-            canvas: Arc::new(Mutex::new(CanvasState::new())),
-            tx,
+            sessions: Arc::new(Mutex::new(store)),
+            next_client_id: Arc::new(AtomicUsize::new(0)),
+            saving: Arc::new(tokio::sync::Mutex::new(())),
         }
     }
 
-    /// Apply a draw event to the canvas and broadcast it
-    fn apply_draw_event(&self, event: DrawEvent) {
-        // Apply to local canvas state
-        {
-            let mut canvas = self.canvas.lock().unwrap();
-            match event.msg_type {
-                0 => {
-                    // Draw line
-                    self.draw_line_on_canvas(
-                        &mut canvas,
-                        event.x0 as usize,
-                        event.y0 as usize,
-                        event.x1 as usize,
-                        event.y1 as usize,
-                        event.is_black == 1,
-                    );
+    fn new_client_id(&self) -> usize {
+        self.next_client_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Join a session loaded by [`load_session`],the other clients are told a participant joined.
+    /// The client that created the session owns it,the others get the role of their `owner_secret` or `key`.
+    /// Nobody owns a session saved before keys existed,everyone joining it edits.
+    /// Returns the role,the messages of the initial sync and the receiver of the session broadcasts,
+    /// all taken under the same lock so no change is missed or sent twice.
+    fn join_session(
+        &self,
+        id: &str,
+        client_id: usize,
+        created: bool,
+        owner_secret: Option<&str>,
+        key: Option<&str>,
+    ) -> Option<(Role, Vec<Vec<u8>>, broadcast::Receiver<Broadcast>)> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions.get_mut(id)?;
+        let role = if created {
+            Role::Owner
+        } else {
+            session.role(owner_secret, key)
+        };
+        let participant = Participant::new(client_id, role);
+        let joined = ServerMessage::Joined {
+            client: client_id as u64,
+            name: participant.name.clone(),
+        };
+        session.broadcast(Some(client_id), joined.to_bytes());
+        session.participants.insert(client_id, participant);
+        Some((role, sync_messages(session, client_id), session.subscribe()))
+    }
+
+    /// Full state of a session for a client that missed broadcasts.
+    /// None if the client is no longer in the session,it was kicked in the messages it missed.
+    fn resync(&self, id: &str, client_id: usize) -> Option<Vec<Vec<u8>>> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions.get_mut(id)?;
+        if !session.participants.contains_key(&client_id) {
+            return None;
+        }
+        Some(sync_messages(session, client_id))
+    }
+
+    /// Check if a client of a session may change its board,given its role and the lock
+    fn can_edit(&self, id: &str, client_id: usize) -> bool {
+        let mut sessions = self.sessions.lock().unwrap();
+        let Some(session) = sessions.get_mut(id) else {
+            return false;
+        };
+        session
+            .participants
+            .get(&client_id)
+            .is_some_and(|participant| participant.role.can_edit(session.locked))
+    }
+
+    /// Lock,kick,clear or resize a session for its owner and tell every client
+    fn owner_action(&self, id: &str, client_id: usize, action: OwnerAction) {
+        let mut sessions = self.sessions.lock().unwrap();
+        let Some(session) = sessions.get_mut(id) else {
+            warn!("Owner action received for unknown session {id}");
+            return;
+        };
+        let is_owner = session
+            .participants
+            .get(&client_id)
+            .is_some_and(|participant| participant.role == Role::Owner);
+        if !is_owner {
+            warn!("Rejected {action:?} from client {client_id},who doesn't own session {id}");
+            return;
+        }
+        match action {
+            OwnerAction::SetLocked { locked } => {
+                info!("🔒 Session {id} is {}", if locked { "locked" } else { "unlocked" });
+                session.locked = locked;
+                session.broadcast(None, ServerMessage::Locked(locked).to_bytes());
+                sessions.mark_unsaved(id);
+            }
+            OwnerAction::Kick { client } => {
+                let kicked = client as usize;
+                if kicked == client_id {
+                    warn!("Rejected the owner of session {id} kicking itself");
+                    return;
+                }
+                let Some(participant) = session.participants.get(&kicked) else {
+                    warn!("Can't kick unknown client {client} of session {id}");
+                    return;
+                };
+                if participant.role == Role::Owner {
+                    warn!("Rejected kicking an owner of session {id}");
+                    return;
                 }
-                2 => {
-                    // Clear canvas
-                    canvas.clear();
+                let participant = session.participants.remove(&kicked).expect("checked above");
+                info!("🥾 {} was removed from session {id}", participant.name);
+                session.kick(kicked, ServerMessage::Kicked { client }.to_bytes());
+                // A kicked editor could join again with the key of its link
+                if participant.role == Role::Editor {
+                    session.rotate_editor_key();
+                    session.broadcast_to_editors(ServerMessage::Keys(session.keys.clone()).to_bytes());
+                    sessions.mark_unsaved(id);
+                    info!("🔑 Editor key of session {id} replaced");
                 }
-                _ => {}
+            }
+            OwnerAction::Clear => {
+                info!("🧽 Session {id} cleared");
+                session.board.clear();
+                broadcast_board(session);
+                sessions.mark_dirty(id);
+            }
+            OwnerAction::Resize {
+                width,
+                height,
+                anchor,
+            } => {
+                if let Err(e) = session.board.resize(width as usize, height as usize, anchor) {
+                    warn!("Rejected resize of session {id}: {e}");
+                    return;
+                }
+                info!("📐 Session {id} resized to {width}x{height} from the {}", anchor.name());
+                broadcast_board(session);
+                sessions.mark_dirty(id);
+            }
+            OwnerAction::Crop {
+                x,
+                y,
+                width,
+                height,
+            } => {
+                if let Err(e) =
+                    session.board.crop(x as usize, y as usize, width as usize, height as usize)
+                {
+                    warn!("Rejected crop of session {id}: {e}");
+                    return;
+                }
+                info!("✂️ Session {id} cropped to {width}x{height} at ({x},{y})");
+                broadcast_board(session);
+                sessions.mark_dirty(id);
+            }
+            OwnerAction::Publish { name } => {
+                // An empty name unlists the session
+                let name = name.as_deref().and_then(clean_name);
+                match &name {
+                    Some(name) => info!("🌐 Session {id} is public as {name}"),
+                    None => info!("🌐 Session {id} is no longer public"),
+                }
+                session.published = name.clone();
+                session.broadcast(None, ServerMessage::Published(name).to_bytes());
+                sessions.mark_unsaved(id);
             }
         }
+    }
 
-        // Broadcast to all connected clients
-        let bytes = event.to_bytes().to_vec();
-        if let Err(e) = self.tx.send(bytes) {
-            warn!("Failed to broadcast draw event: {}", e);
+    /// Remember where a client points and relay it to the other clients
+    fn set_presence(&self, id: &str, client_id: usize, presence: Presence) {
+        let mut sessions = self.sessions.lock().unwrap();
+        let Some(session) = sessions.get_mut(id) else {
+            return;
+        };
+        let Some(participant) = session.participants.get_mut(&client_id) else {
+            return;
+        };
+        // Relayed to everyone and sent again on every join,it must stay small
+        let presence = presence.clipped();
+        participant.presence = presence.clone();
+        let message = ServerMessage::Presence {
+            client: client_id as u64,
+            presence,
+        };
+        session.broadcast(Some(client_id), message.to_bytes());
+    }
+
+    /// Change the display name of a client and relay it to the other clients
+    fn set_name(&self, id: &str, client_id: usize, name: &str) {
+        let Some(name) = clean_name(name) else {
+            warn!("Rejected empty name from client {client_id}");
+            return;
+        };
+        let mut sessions = self.sessions.lock().unwrap();
+        let Some(session) = sessions.get_mut(id) else {
+            return;
+        };
+        let Some(participant) = session.participants.get_mut(&client_id) else {
+            return;
+        };
+        info!("🏷️ Client {client_id} of session {id} is now {name}");
+        participant.name = name.clone();
+        let message = ServerMessage::Joined {
+            client: client_id as u64,
+            name,
+        };
+        session.broadcast(Some(client_id), message.to_bytes());
+    }
+
+    /// Post a message on the chat of a session,attributed to the display name of the client
+    fn post_chat(&self, id: &str, client_id: usize, text: &str) {
+        let Some(text) = clean_chat(text) else {
+            return;
+        };
+        let mut sessions = self.sessions.lock().unwrap();
+        let Some(session) = sessions.get_mut(id) else {
+            warn!("Chat message received for unknown session {id}");
+            return;
+        };
+        let Some(participant) = session.participants.get(&client_id) else {
+            return;
+        };
+        let message = ChatMessage {
+            name: participant.name.clone(),
+            text,
+            sent_at: unix_millis(SystemTime::now()),
+        };
+        session.broadcast(None, ServerMessage::Chat(message.clone()).to_bytes());
+        session.push_chat(message);
+        sessions.mark_unsaved(id);
+    }
+
+    /// Remove a client from the participants of a session and tell the other clients
+    fn leave_session(&self, id: &str, client_id: usize) {
+        let mut sessions = self.sessions.lock().unwrap();
+        let Some(session) = sessions.get_mut(id) else {
+            return;
+        };
+        if let Some(participant) = session.participants.remove(&client_id) {
+            info!("👋 {} left session {id}", participant.name);
         }
+        let message = ServerMessage::Left {
+            client: client_id as u64,
+        };
+        session.broadcast(Some(client_id), message.to_bytes());
     }
 
-    /// Draw line using Bresenham's algorithm (same as frontend)
-    fn draw_line_on_canvas(
-        &self,
-        canvas: &mut CanvasState,
-        x0: usize,
-        y0: usize,
-        x1: usize,
-        y1: usize,
-        is_black: bool,
-    ) {
-        let (mut x0, mut y0, x1, y1) = (x0 as isize, y0 as isize, x1 as isize, y1 as isize);
-
-        let dx = (x1 - x0).abs();
-        let dy = -(y1 - y0).abs();
-        let sx = if x0 < x1 { 1 } else { -1 };
-        let sy = if y0 < y1 { 1 } else { -1 };
-        let mut err = dx + dy;
+    /// Replace the palette of a session and relay it to the other clients
+    fn set_palette(&self, id: &str, client_id: usize, mut palette: Palette) {
+        if palette.colors.is_empty() {
+            // Nothing could be imported or drawn with it
+            warn!("Rejected empty palette for session {id}");
+            return;
+        }
+        if palette.colors.len() > MAX_PALETTE_COLORS {
+            warn!("Rejected palette with {} colors for session {id}", palette.colors.len());
+            return;
+        }
+        palette.clip_name();
+        {
+            let mut sessions = self.sessions.lock().unwrap();
+            let Some(session) = sessions.get_mut(id) else {
+                warn!("Palette received for unknown session {id}");
+                return;
+            };
+            session.palette = Some(palette.clone());
+            session.broadcast(Some(client_id), ServerMessage::Palette(palette).to_bytes());
+            sessions.mark_dirty(id);
+        }
+    }
 
-        loop {
-            if x0 >= 0 && y0 >= 0 && x0 < GRID_SIZE && y0 < 100 {
-                canvas.set_pixel(x0 as usize, y0 as usize, is_black);
+    /// Merge a client's delta in the session canvas and relay it to the other clients.
+    /// Returns the acknowledgement for the sender.
+    fn apply_delta(&self, id: &str, client_id: usize, delta: CanvasDelta) -> Option<Vec<u8>> {
+        let ack = ServerMessage::Ack { seq: delta.seq }.to_bytes();
+        {
+            let mut sessions = self.sessions.lock().unwrap();
+            let Some(session) = sessions.get_mut(id) else {
+                warn!("Delta received for unknown session {id}");
+                return None;
+            };
+            if !delta.fits(session.board.dimension()) {
+                // Drawn before a resize or a crop,its pixels would land in the wrong place
+                warn!(
+                    "Dropped delta drawn on a {}x{} board for session {id}",
+                    delta.width, delta.height
+                );
+                return Some(ack);
+            }
+            // Every frame has the same layers,locked in all of them at once
+            let locked = session
+                .board
+                .first()
+                .layers
+                .get(delta.layer)
+                .is_some_and(|layer| layer.info.locked);
+            if locked {
+                // Drawn before the lock reached the client,or by a client ignoring it
+                warn!("Dropped delta drawn on locked layer {} of session {id}", delta.layer);
+                return Some(ack);
             }
+            let Some(canvas) = session.board.canvas_mut(delta.frame, delta.layer) else {
+                // The frame or layer was removed while the client was drawing on it
+                warn!(
+                    "Delta received for unknown layer {} of frame {} of session {id}",
+                    delta.layer, delta.frame
+                );
+                return Some(ack);
+            };
+            delta.apply(canvas);
+            session.revision += 1;
+            let relayed = CanvasDelta {
+                seq: session.revision,
+                frame: delta.frame,
+                layer: delta.layer,
+                runs: delta.runs,
+                width: delta.width,
+                height: delta.height,
+            };
+            session.broadcast(Some(client_id), ServerMessage::Delta(relayed).to_bytes());
+            sessions.mark_dirty(id);
+        }
+        Some(ack)
+    }
 
-            if x0 == x1 && y0 == y1 {
-                break;
+    /// Change the layer structure of a session and send the op to every client,sender included,
+    /// new layers get their id here
+    fn apply_layer_op(&self, id: &str, mut op: LayerOp) {
+        {
+            let mut sessions = self.sessions.lock().unwrap();
+            let Some(session) = sessions.get_mut(id) else {
+                warn!("Layer change received for unknown session {id}");
+                return;
+            };
+            session.board.assign_layer_id(&mut op);
+            if let Err(e) = session.board.apply_layer_op(&op) {
+                warn!("Rejected layer change {op:?} for session {id}: {e}");
+                return;
             }
+            session.revision += 1;
+            session.broadcast(None, ServerMessage::Layer(op).to_bytes());
+            sessions.mark_dirty(id);
+        }
+    }
 
-            let e2 = 2 * err;
-            if e2 >= dy {
-                err += dy;
-                x0 += sx;
+    /// Change the animation frames of a session and send the op to every client,sender included,
+    /// new frames get their id here
+    fn apply_frame_op(&self, id: &str, mut op: FrameOp) {
+        {
+            let mut sessions = self.sessions.lock().unwrap();
+            let Some(session) = sessions.get_mut(id) else {
+                warn!("Frame change received for unknown session {id}");
+                return;
+            };
+            session.board.assign_frame_id(&mut op);
+            if let Err(e) = session.board.apply_frame_op(&op) {
+                warn!("Rejected frame change {op:?} for session {id}: {e}");
+                return;
             }
-            if e2 <= dx {
-                err += dx;
-                y0 += sy;
+            session.revision += 1;
+            session.broadcast(None, ServerMessage::Frame(op).to_bytes());
+            sessions.mark_dirty(id);
+        }
+    }
+
+    /// Merge a client's drawing layer in the bottom layer of the first frame and broadcast the result
+    fn apply_drawing(&self, id: &str, data: &[u8]) {
+        {
+            let mut sessions = self.sessions.lock().unwrap();
+            let Some(session) = sessions.get_mut(id) else {
+                warn!("Drawing received for unknown session {id}");
+                return;
+            };
+            let (width, height) = session.board.dimension();
+            let mut drawing = DrawingPixelCanvas::new(width, height);
+            if let Err(e) = drawing.assign_bytes(data) {
+                warn!("Invalid drawing data for session {id}: {e}");
+                return;
             }
+            session.board.first_mut().layers.bottom_mut().canvas.merge_top(&drawing);
+            session.revision += 1;
+            let bytes = ServerMessage::Snapshot(snapshot::encode(&session.board)).to_bytes();
+            session.broadcast(None, bytes);
+            sessions.mark_dirty(id);
         }
     }
+}
+
+/// Count a change of the whole board and send everyone its snapshot
+fn broadcast_board(session: &mut Session) {
+    session.revision += 1;
+    let bytes = ServerMessage::Snapshot(snapshot::encode(&session.board)).to_bytes();
+    session.broadcast(None, bytes);
+}
 
-    /// Get current canvas state for new clients
-    fn get_canvas_state(&self) -> CanvasState {
-        let canvas = self.canvas.lock().unwrap();
-        CanvasState::from_bytes(canvas.to_bytes())
+/// Messages bringing a client up to date with a session:
+/// its id and role,the lock,the public name,the snapshot,the palette,the chat and the other participants with their cursor,
+/// then [`ServerMessage::Synced`]
+fn sync_messages(session: &Session, client_id: usize) -> Vec<Vec<u8>> {
+    let role = session
+        .participants
+        .get(&client_id)
+        .map_or(Role::Viewer, |participant| participant.role);
+    let welcome = ServerMessage::Welcome {
+        client: client_id as u64,
+        role,
+        keys: (role != Role::Viewer).then(|| session.keys.clone()),
+        owner_secret: (role == Role::Owner).then(|| session.owner_secret.clone()),
+    };
+    let mut messages = vec![
+        welcome.to_bytes(),
+        ServerMessage::Locked(session.locked).to_bytes(),
+        ServerMessage::Published(session.published.clone()).to_bytes(),
+        ServerMessage::Snapshot(snapshot::encode(&session.board)).to_bytes(),
+    ];
+    if let Some(palette) = &session.palette {
+        messages.push(ServerMessage::Palette(palette.clone()).to_bytes());
     }
+    messages.push(ServerMessage::ChatHistory(session.chat.iter().cloned().collect()).to_bytes());
+    for (client, participant) in &session.participants {
+        if *client == client_id {
+            continue;
+        }
+        let client = *client as u64;
+        let joined = ServerMessage::Joined {
+            client,
+            name: participant.name.clone(),
+        };
+        let presence = ServerMessage::Presence {
+            client,
+            presence: participant.presence.clone(),
+        };
+        messages.push(joined.to_bytes());
+        messages.push(presence.to_bytes());
+    }
+    messages.push(ServerMessage::Synced.to_bytes());
+    messages
 }
 
 #[tokio::main]
@@ -121,45 +496,215 @@ async fn main() {
     // Initialize tracing
     tracing_subscriber::fmt::init();
 
-    let app_state = AppState::new();
+    let store = SessionStore::new(DEFAULT_SESSION_DIR).expect("Failed to open session directory");
+    let app_state = AppState::new(store);
+
+    // Periodically write changed sessions to disk and drop the idle ones from memory
+    let save_state = app_state.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SAVE_INTERVAL);
+        loop {
+            interval.tick().await;
+            flush_sessions(&save_state).await;
+            save_state.sessions.lock().unwrap().evict_idle(IDLE_TIMEOUT);
+        }
+    });
 
     // Build our application with routes
     let app = Router::new()
         .route("/ws", get(websocket_handler))
-        .route("/", get(serve_index))
-        .nest_service("/dist", get_service(ServeDir::new("dist")))
-        .nest_service("/styles.css", get_service(ServeFile::new("styles.css")))
+        .route("/ws/:id", get(session_websocket_handler))
+        .route("/canvas/:file", get(canvas_png_handler))
+        .route(SESSIONS_PATH, get(public_sessions_handler))
+        .route(&format!("{SESSIONS_PATH}/:id"), get(session_listing_handler))
+        // The frontend built by `trunk build`,every other path is a page of the app
+        .fallback_service(
+            ServeDir::new(DIST_DIR).fallback(ServeFile::new(format!("{DIST_DIR}/index.html"))),
+        )
         .layer(
             ServiceBuilder::new()
                 .layer(CorsLayer::permissive())
         )
-        .with_state(app_state);
+        .with_state(app_state.clone());
 
     let listener = tokio::net::TcpListener::bind("127.0.0.1:3000")
         .await
         .unwrap();
 
     info!("🚀 Collaborative drawing server listening on http://127.0.0.1:3000");
-    axum::serve(listener, app).await.unwrap();
-}
-
-/// Serve the main HTML page
-async fn serve_index() -> impl IntoResponse {
-    Html(std::fs::read_to_string("index.html").unwrap_or_else(|_| {
-        r#"<!DOCTYPE html>
-<html>
-<head>
-    <title>Collaborative Drawing Canvas</title>
-    <link rel="stylesheet" href="/styles.css">
-</head>
-<body>
-    <script type="module">
-        import init from '/dist/rust_web.js';
-        init();
-    </script>
-</body>
-</html>"#.to_string()
-    }))
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal())
+        .await
+        .unwrap();
+
+    // Save whatever changed since the last periodic flush
+    flush_sessions(&app_state).await;
+    info!("💾 Sessions saved,server stopped");
+}
+
+/// Write every changed session to disk.
+/// They are serialized under the lock but written by a blocking task,so clients are never held up by the disk.
+async fn flush_sessions(state: &AppState) {
+    let _saving = state.saving.lock().await;
+    let jobs = state.sessions.lock().unwrap().take_saves();
+    if jobs.is_empty() {
+        return;
+    }
+    let ids: Vec<String> = jobs.iter().map(|job| job.id.clone()).collect();
+    let failed = tokio::task::spawn_blocking(move || {
+        jobs.into_iter()
+            .filter_map(|job| {
+                job.write()
+                    .inspect_err(|e| error!("Failed to save session {}: {e}", job.id))
+                    .err()
+                    .map(|_| job.id)
+            })
+            .collect::<Vec<_>>()
+    })
+    .await
+    .unwrap_or_else(|e| {
+        error!("Saving sessions panicked: {e}");
+        ids
+    });
+    // The next flush tries them again
+    let mut sessions = state.sessions.lock().unwrap();
+    for id in failed {
+        sessions.mark_unsaved(&id);
+    }
+}
+
+/// Resolve when the server is asked to stop (ctrl-c)
+async fn shutdown_signal() {
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        error!("Failed to listen for shutdown signal: {}", e);
+    }
+}
+
+#[derive(Deserialize)]
+struct PngQuery {
+    /// Size of a cell in pixels,1 by default
+    scale: Option<u32>,
+    /// Index of the frame,the first frame by default
+    frame: Option<usize>,
+}
+
+/// Png of the visible layers of a session frame,`/canvas/<uuid>.png?scale=N&frame=I`.
+/// Answers 304 when the `If-None-Match` or `If-Modified-Since` of the request is still current.
+async fn canvas_png_handler(
+    Path(file): Path<String>,
+    Query(query): Query<PngQuery>,
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> Response {
+    let Some(id) = file.strip_suffix(".png") else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if load_session(&state, id, None).await.is_none() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let frame_index = query.frame.unwrap_or(0);
+    let (canvas, modified) = {
+        let sessions = state.sessions.lock().unwrap();
+        // Evicted since it was loaded,it has no client
+        let Some(session) = sessions.get(id) else {
+            return StatusCode::NOT_FOUND.into_response();
+        };
+        let Some(frame) = session.board.frames().get(frame_index) else {
+            return StatusCode::NOT_FOUND.into_response();
+        };
+        (frame.layers.flatten(), session.modified)
+    };
+    let scale = query.scale.unwrap_or(1);
+    // Hashed from the pixels,so it stays right when the session is loaded again
+    let etag = render::etag(&canvas, scale);
+    let last_modified = httpdate::fmt_http_date(modified);
+
+    let etag_matches = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.split(',').any(|tag| tag.trim() == etag || tag.trim() == "*"));
+    let not_modified = match etag_matches {
+        Some(matches) => matches,
+        // If-Modified-Since is only used without If-None-Match,the dates have a one second precision
+        None => headers
+            .get(header::IF_MODIFIED_SINCE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| httpdate::parse_http_date(value).ok())
+            .is_some_and(|since| {
+                modified.duration_since(since).unwrap_or_default() < Duration::from_secs(1)
+            }),
+    };
+    let cache_headers = [
+        (header::ETAG, etag),
+        (header::LAST_MODIFIED, last_modified),
+        // Cached copies are checked again on every use,a board changes at any time
+        (header::CACHE_CONTROL, "no-cache".to_string()),
+    ];
+    if not_modified {
+        return (StatusCode::NOT_MODIFIED, cache_headers).into_response();
+    }
+
+    match tokio::task::spawn_blocking(move || render::png(&canvas, scale)).await {
+        Ok(Ok(bytes)) => (
+            cache_headers,
+            [(header::CONTENT_TYPE, "image/png")],
+            bytes,
+        )
+            .into_response(),
+        Ok(Err(e)) => {
+            error!("Failed to render session {id}: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+        Err(e) => {
+            error!("Render task of session {id} failed: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Json list of the public sessions,the last changed first
+async fn public_sessions_handler(State(state): State<AppState>) -> Json<Vec<SessionListing>> {
+    Json(state.sessions.lock().unwrap().published())
+}
+
+/// Json listing of a session,`/sessions/<uuid>`.
+/// Anyone with the uuid may see it,like its png.
+async fn session_listing_handler(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<SessionListing>, StatusCode> {
+    let id = session::normalize_id(&id).ok_or(StatusCode::NOT_FOUND)?;
+    if load_session(&state, &id, None).await.is_none() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let sessions = state.sessions.lock().unwrap();
+    let session = sessions.get(&id).ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(session.listing(&id)))
+}
+
+/// Make sure a session is in memory,loading it from disk or creating it with the size of `create`.
+/// Returns None if it doesn't exist and isn't created,otherwise whether this call created it.
+/// A session that isn't loaded is read by a blocking task,the lock is never held on the disk.
+async fn load_session(state: &AppState, id: &str, create: Option<(usize, usize)>) -> Option<bool> {
+    let loader = {
+        let sessions = state.sessions.lock().unwrap();
+        if sessions.get(id).is_some() {
+            return Some(false);
+        }
+        sessions.loader(id)?
+    };
+    let loader = match create {
+        Some((width, height)) => loader.or_create(width, height),
+        None => loader,
+    };
+    match tokio::task::spawn_blocking(move || loader.load()).await {
+        Ok(Some(loaded)) => Some(state.sessions.lock().unwrap().insert_loaded(loaded)),
+        Ok(None) => None,
+        Err(e) => {
+            error!("Loading session {id} failed: {e}");
+            None
+        }
+    }
 }
 
 /// WebSocket handler for real-time drawing updates
@@ -167,49 +712,211 @@ async fn websocket_handler(
     ws: WebSocketUpgrade,
     State(state): State<AppState>,
 ) -> impl IntoResponse {
-    ws.on_upgrade(|socket| handle_socket(socket, state))
+    ws.on_upgrade(|socket| handle_socket(socket, state, None))
 }
 
-/// Handle individual WebSocket connection
-async fn handle_socket(socket: WebSocket, state: AppState) {
-    let (mut sender, mut receiver) = socket.split();
-    let mut rx = state.tx.subscribe();
+/// WebSocket handler bound to one session through the url,`/ws/<uuid>`.
+/// The session in the url lets a reverse proxy route each session.
+async fn session_websocket_handler(
+    ws: WebSocketUpgrade,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    ws.on_upgrade(|socket| handle_socket(socket, state, Some(id)))
+}
 
-    info!("🔗 New client connected");
+/// Message for the task writing to a client's websocket
+enum Outgoing {
+    /// Sent to this client only
+    Direct(Vec<u8>),
+    /// The client joined a session: its initial sync,then the broadcasts of the session
+    Join {
+        id: String,
+        messages: Vec<Vec<u8>>,
+        rx: broadcast::Receiver<Broadcast>,
+    },
+}
 
-    // Send current canvas state to new client
-    let canvas_state = state.get_canvas_state();
-    let full_sync_bytes = [&[1u8], canvas_state.to_bytes().as_slice()].concat();
-    if let Err(e) = sender.send(Message::Binary(full_sync_bytes)).await {
-        error!("Failed to send initial canvas state: {}", e);
-        return;
+/// Next broadcast of the joined session,never resolves before a session is joined
+async fn recv_broadcast(
+    joined: &mut Option<(String, broadcast::Receiver<Broadcast>)>,
+) -> Result<Broadcast, RecvError> {
+    match joined {
+        Some((_, rx)) => rx.recv().await,
+        None => std::future::pending().await,
     }
+}
+
+/// Handle individual WebSocket connection
+/// `url_session` is the session of the url,when the client connected on `/ws/<uuid>`
+async fn handle_socket(socket: WebSocket, state: AppState, url_session: Option<String>) {
+    let (mut sender, mut receiver) = socket.split();
+    let client_id = state.new_client_id();
+
+    info!("🔗 New client {client_id} connected");
 
     // Spawn task to handle broadcasting to this client
-    let broadcast_task = tokio::spawn(async move {
-        while let Ok(msg) = rx.recv().await {
-            if sender.send(Message::Binary(msg)).await.is_err() {
-                break;
+    // The initial sync is sent before the session broadcasts are read,so it never races with them
+    let (direct_tx, mut direct_rx) = tokio::sync::mpsc::unbounded_channel::<Outgoing>();
+    let task_state = state.clone();
+    let mut broadcast_task = tokio::spawn(async move {
+        let mut joined: Option<(String, broadcast::Receiver<Broadcast>)> = None;
+        loop {
+            let messages = tokio::select! {
+                outgoing = direct_rx.recv() => match outgoing {
+                    Some(Outgoing::Direct(bytes)) => vec![bytes],
+                    Some(Outgoing::Join { id, messages, rx }) => {
+                        joined = Some((id, rx));
+                        messages
+                    }
+                    None => break,
+                },
+                received = recv_broadcast(&mut joined) => match received {
+                    Ok(Broadcast { bytes, kick, .. }) if kick == Some(client_id) => {
+                        // Removed by the owner,tell the client before hanging up
+                        let _ = sender.send(Message::Binary(bytes)).await;
+                        let _ = sender.send(Message::Close(None)).await;
+                        return;
+                    }
+                    Ok(Broadcast { from, .. }) if from == Some(client_id) => continue,
+                    Ok(Broadcast { to: Some(to), .. }) if !to.contains(&client_id) => continue,
+                    Ok(Broadcast { bytes, .. }) => vec![bytes],
+                    Err(RecvError::Lagged(skipped)) => {
+                        // The receiver moved on to the oldest buffered message,
+                        // the full state covers the skipped ones and replaying the rest changes nothing
+                        let Some((id, _)) = &joined else {
+                            continue;
+                        };
+                        warn!("Client {client_id} missed {skipped} messages of session {id},resyncing");
+                        match task_state.resync(id, client_id) {
+                            Some(messages) => messages,
+                            None => {
+                                // Kicked in the skipped messages
+                                let kicked = ServerMessage::Kicked { client: client_id as u64 };
+                                let _ = sender.send(Message::Binary(kicked.to_bytes())).await;
+                                let _ = sender.send(Message::Close(None)).await;
+                                return;
+                            }
+                        }
+                    }
+                    Err(RecvError::Closed) => {
+                        joined = None;
+                        continue;
+                    }
+                },
+            };
+            for msg in messages {
+                if sender.send(Message::Binary(msg)).await.is_err() {
+                    return;
+                }
             }
         }
     });
 
+    // Session the client joined through DataPass::Whid
+    let mut session_id: Option<String> = None;
+    // Capability key of the page link and secret of the owner,sent before joining
+    let mut key: Option<String> = None;
+    let mut owner_secret: Option<String> = None;
+
     // Handle incoming messages from this client
-    while let Some(msg) = receiver.next().await {
+    loop {
+        let msg = tokio::select! {
+            msg = receiver.next() => match msg {
+                Some(msg) => msg,
+                None => break,
+            },
+            // The writer stops when the client is gone or was kicked
+            _ = &mut broadcast_task => break,
+        };
         match msg {
             Ok(Message::Binary(data)) => {
-                if data.len() == 9 {
-                    // Parse draw event
-                    let mut event_bytes = [0u8; 9];
-                    event_bytes.copy_from_slice(&data);
-                    let event = DrawEvent::from_bytes(&event_bytes);
-                    
-                    info!("📝 Received draw event: {:?}", event);
-                    
-                    // Apply and broadcast the event
-                    state.apply_draw_event(event);
-                } else {
-                    warn!("Received invalid binary message length: {}", data.len());
+                match ClientMessage::from_bytes(&data) {
+                    Ok(ClientMessage::Pass(DataPass::Whid { width, height, id })) => {
+                        if url_session.as_ref().is_some_and(|url_id| *url_id != id) {
+                            warn!("Rejected session {id},the connection is bound to {url_session:?}");
+                            continue;
+                        }
+                        if let Some(previous) = session_id.take() {
+                            state.leave_session(&previous, client_id);
+                        }
+                        let Some(created) = load_session(&state, &id, Some((width, height))).await else {
+                            warn!("Rejected join of session {id}");
+                            continue;
+                        };
+                        let Some((role, messages, rx)) = state.join_session(
+                            &id,
+                            client_id,
+                            created,
+                            owner_secret.as_deref(),
+                            key.as_deref(),
+                        ) else {
+                            warn!("Rejected join of session {id}");
+                            continue;
+                        };
+                        info!("🖼️ Client joined session {id} as {role:?}");
+                        let _ = direct_tx.send(Outgoing::Join {
+                            id: id.clone(),
+                            messages,
+                            rx,
+                        });
+                        session_id = Some(id);
+                    }
+                    Ok(ClientMessage::Key(page_key)) => key = Some(page_key),
+                    Ok(ClientMessage::OwnerSecret(secret)) => owner_secret = Some(secret),
+                    Ok(message)
+                        if message.is_write()
+                            && !session_id.as_deref().is_some_and(|id| state.can_edit(id, client_id)) =>
+                    {
+                        warn!("Rejected a change from client {client_id},it may not edit");
+                    }
+                    Ok(ClientMessage::Pass(DataPass::Canvas { data })) => match &session_id {
+                        Some(id) => state.apply_drawing(id, &data),
+                        None => warn!("Canvas received before joining a session"),
+                    },
+                    Ok(ClientMessage::Delta(delta)) => match &session_id {
+                        Some(id) => {
+                            if let Some(ack) = state.apply_delta(id, client_id, delta) {
+                                let _ = direct_tx.send(Outgoing::Direct(ack));
+                            }
+                        }
+                        None => warn!("Delta received before joining a session"),
+                    },
+                    Ok(ClientMessage::Palette(palette)) => match &session_id {
+                        Some(id) => {
+                            info!("🎨 Palette of session {id} set to {}", palette.name);
+                            state.set_palette(id, client_id, palette);
+                        }
+                        None => warn!("Palette received before joining a session"),
+                    },
+                    Ok(ClientMessage::Layer(op)) => match &session_id {
+                        Some(id) => state.apply_layer_op(id, op),
+                        None => warn!("Layer change received before joining a session"),
+                    },
+                    Ok(ClientMessage::Frame(op)) => match &session_id {
+                        Some(id) => state.apply_frame_op(id, op),
+                        None => warn!("Frame change received before joining a session"),
+                    },
+                    Ok(ClientMessage::Presence(presence)) => {
+                        if let Some(id) = &session_id {
+                            state.set_presence(id, client_id, presence);
+                        }
+                    }
+                    Ok(ClientMessage::Name(name)) => match &session_id {
+                        Some(id) => state.set_name(id, client_id, &name),
+                        None => warn!("Name received before joining a session"),
+                    },
+                    Ok(ClientMessage::Chat(text)) => match &session_id {
+                        Some(id) => state.post_chat(id, client_id, &text),
+                        None => warn!("Chat message received before joining a session"),
+                    },
+                    Ok(ClientMessage::Owner(action)) => match &session_id {
+                        Some(id) => state.owner_action(id, client_id, action),
+                        None => warn!("Owner action received before joining a session"),
+                    },
+                    Err(e) => {
+                        warn!("Received invalid binary message: {}", e);
+                    }
                 }
             }
             Ok(Message::Close(_)) => {
@@ -228,5 +935,8 @@ async fn handle_socket(socket: WebSocket, state: AppState) {
 
     // Clean up
     broadcast_task.abort();
+    if let Some(id) = &session_id {
+        state.leave_session(id, client_id);
+    }
     info!("🧹 Client connection cleaned up");
 }
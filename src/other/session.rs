@@ -0,0 +1,676 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use frontend::{
+    board::{Board, check_dimension},
+    palette::{Palette, PaletteFormat},
+    protocol::{ChatMessage, MAX_CHAT_HISTORY, Presence, Role, SessionKeys, SessionListing},
+    snapshot,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+/// Directory the sessions are written to when no other path is given
+pub const DEFAULT_SESSION_DIR: &str = "sessions";
+/// Extension of a stored session file
+const SESSION_FILE_EXTENSION: &str = "canvas";
+/// Format of the shared palette,stored next to the session file
+const PALETTE_FORMAT: PaletteFormat = PaletteFormat::Gpl;
+/// Extension of the chat history,a json array stored next to the session file
+const CHAT_FILE_EXTENSION: &str = "chat";
+/// Extension of the capability keys,lock and public name,stored as json next to the session file
+const KEYS_FILE_EXTENSION: &str = "keys";
+/// Extension a session file that can't be decoded is moved to,so it is never overwritten
+const CORRUPT_FILE_EXTENSION: &str = "corrupt";
+/// Width and height header of the session files written before snapshots,
+/// both stored as little-endian u32
+const LEGACY_HEADER_LEN: usize = 8;
+/// Messages buffered for the slowest client of a session before it lags behind
+const CHANNEL_CAPACITY: usize = 1000;
+
+/// Message broadcasted to the clients of a session
+#[derive(Clone, Debug)]
+pub struct Broadcast {
+    /// Client the message came from,it already has the change so it is skipped
+    pub from: Option<usize>,
+    pub bytes: Vec<u8>,
+    /// Client whose connection is closed once it got the message
+    pub kick: Option<usize>,
+    /// Only these clients get the message,None for everyone
+    pub to: Option<Vec<usize>>,
+}
+
+/// A single drawing board, identified by the uuid of the drawing page
+pub struct Session {
+    pub board: Board,
+    /// Number of changes merged since the session was loaded
+    pub revision: u64,
+    /// Palette shared by the collaborators,None until one is set
+    pub palette: Option<Palette>,
+    /// Time of the last change,kept across restarts through the session file
+    pub modified: SystemTime,
+    /// Broadcast channel of the clients of this session
+    tx: broadcast::Sender<Broadcast>,
+    /// Since when no client is subscribed,None while some are
+    idle_since: Option<Instant>,
+    /// Connected clients by id,never saved
+    pub participants: BTreeMap<usize, Participant>,
+    /// Last messages of the chat,oldest first
+    pub chat: VecDeque<ChatMessage>,
+    /// Keys of the editor and viewer links
+    pub keys: SessionKeys,
+    /// Secret of the owner,only told to the client creating the session
+    pub owner_secret: String,
+    /// Only the owner may change the board
+    pub locked: bool,
+    /// Name the session is publicly listed under,None while it isn't public
+    pub published: Option<String>,
+    /// Saved before keys existed,everyone with its url edits it as before and nobody owns it
+    pub open: bool,
+}
+
+/// Content of the keys file
+#[derive(Serialize, Deserialize)]
+struct KeysFile {
+    #[serde(flatten)]
+    keys: SessionKeys,
+    /// Sessions saved before owners existed get a secret nobody knows,they have no owner
+    #[serde(default = "new_key")]
+    owner: String,
+    #[serde(default)]
+    locked: bool,
+    #[serde(default)]
+    published: Option<String>,
+    /// The session was saved before keys existed,see [`Session::open`]
+    #[serde(default)]
+    open: bool,
+    /// Size of the board,so public sessions are listed without reading their board.
+    /// Zero in keys files saved before it was stored.
+    #[serde(default)]
+    width: u32,
+    #[serde(default)]
+    height: u32,
+}
+
+/// Files of a changed session,serialized under the lock so they can be written without it
+pub struct SaveJob {
+    pub id: String,
+    files: Vec<(PathBuf, Vec<u8>)>,
+}
+
+impl SaveJob {
+    /// Write every file of the session,blocking
+    pub fn write(&self) -> io::Result<()> {
+        for (path, data) in &self.files {
+            // Write to a temporary file first,so a crash never leaves a half written file
+            let tmp_path = path.with_extension("tmp");
+            fs::write(&tmp_path, data)?;
+            fs::rename(&tmp_path, path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads a session from disk without the store,so it can run in a blocking task
+pub struct SessionLoader {
+    id: String,
+    path: PathBuf,
+    /// Size of the board created when the session was never saved,None to only load a saved one
+    create: Option<(usize, usize)>,
+}
+
+/// A session read or created by a [`SessionLoader`]
+pub struct LoadedSession {
+    id: String,
+    session: Session,
+    /// New board,it must be saved
+    created: bool,
+    /// Its keys were made while loading,they must be saved
+    unsaved: bool,
+}
+
+impl SessionLoader {
+    /// Create a board of the given size when there is no saved session
+    pub fn or_create(self, width: usize, height: usize) -> Self {
+        Self {
+            create: Some((width, height)),
+            ..self
+        }
+    }
+
+    /// Read the session with its palette,chat and keys,blocking.
+    /// A session file that can't be decoded is moved aside first,see [`CORRUPT_FILE_EXTENSION`].
+    /// Returns None if there is no session and none is created,if a new session would have an invalid size
+    /// or if the session file can't be read.
+    pub fn load(self) -> Option<LoadedSession> {
+        let Self { id, path, create } = self;
+        let loaded = match fs::read(&path) {
+            Ok(data) => match Session::from_bytes(&data) {
+                Ok(mut session) => {
+                    info!("📂 Loaded session {id} from disk");
+                    session.modified = file_modified(&path);
+                    Some(session)
+                }
+                Err(e) => {
+                    warn!("Corrupted session file {}: {e}", path.display());
+                    let corrupt_path = path.with_extension(CORRUPT_FILE_EXTENSION);
+                    match fs::rename(&path, &corrupt_path) {
+                        Ok(()) => warn!("🗄️ Moved it to {}", corrupt_path.display()),
+                        // Moved by another loader in the meantime
+                        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                        Err(e) => {
+                            error!("Failed to move {} aside: {e}", path.display());
+                            return None;
+                        }
+                    }
+                    None
+                }
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => {
+                error!("Failed to read session file {}: {e}", path.display());
+                return None;
+            }
+        };
+        let (mut session, created) = match loaded {
+            Some(session) => (session, false),
+            None => {
+                let (width, height) = create?;
+                if let Err(e) = check_dimension(width, height) {
+                    warn!("Refused to create session {id}: {e}");
+                    return None;
+                }
+                info!("🆕 Created session {id} ({width}x{height})");
+                (Session::new(width, height), true)
+            }
+        };
+        // A board replacing a corrupted one keeps its keys,palette,chat and public name
+        let has_keys = load_extras(&path, &id, &mut session);
+        // A board saved before keys existed gets new ones,they must be saved
+        let unsaved = !has_keys && !created;
+        if unsaved {
+            session.open = true;
+        }
+        Some(LoadedSession {
+            id,
+            session,
+            created,
+            unsaved,
+        })
+    }
+}
+
+/// A client connected to a session
+pub struct Participant {
+    pub name: String,
+    pub role: Role,
+    /// Last reported cursor
+    pub presence: Presence,
+}
+
+impl Participant {
+    /// Named after its id until it sends its display name
+    pub fn new(client_id: usize, role: Role) -> Self {
+        Self {
+            name: format!("Guest {client_id}"),
+            role,
+            presence: Presence::default(),
+        }
+    }
+}
+
+impl Session {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self::with_board(Board::new(width, height))
+    }
+
+    fn with_board(board: Board) -> Self {
+        let (tx, _rx) = broadcast::channel(CHANNEL_CAPACITY);
+        Self {
+            board,
+            revision: 0,
+            palette: None,
+            modified: SystemTime::now(),
+            tx,
+            idle_since: None,
+            participants: BTreeMap::new(),
+            chat: VecDeque::new(),
+            keys: SessionKeys {
+                editor: new_key(),
+                viewer: new_key(),
+            },
+            owner_secret: new_key(),
+            locked: false,
+            published: None,
+            open: false,
+        }
+    }
+
+    /// How the session is shown in the lists of sessions
+    pub fn listing(&self, id: &str) -> SessionListing {
+        let (width, height) = self.board.dimension();
+        SessionListing {
+            id: id.to_string(),
+            name: self.published.clone(),
+            width: width as u32,
+            height: height as u32,
+            modified: unix_millis(self.modified),
+        }
+    }
+
+    /// Role given by the owner secret or the key of a link,links without the editor key only view
+    pub fn role(&self, owner_secret: Option<&str>, key: Option<&str>) -> Role {
+        if owner_secret == Some(self.owner_secret.as_str()) {
+            Role::Owner
+        } else if self.open || key == Some(self.keys.editor.as_str()) {
+            Role::Editor
+        } else {
+            Role::Viewer
+        }
+    }
+
+    /// Add a message to the chat,dropping the oldest past [`MAX_CHAT_HISTORY`]
+    pub fn push_chat(&mut self, message: ChatMessage) {
+        self.chat.push_back(message);
+        while self.chat.len() > MAX_CHAT_HISTORY {
+            self.chat.pop_front();
+        }
+    }
+
+    /// Receive the messages broadcasted to this session from now on
+    pub fn subscribe(&mut self) -> broadcast::Receiver<Broadcast> {
+        self.idle_since = None;
+        self.tx.subscribe()
+    }
+
+    /// Send a message to every client of this session,except `from`
+    pub fn broadcast(&self, from: Option<usize>, bytes: Vec<u8>) {
+        // Sending only fails when nobody is subscribed,there is nobody to tell then
+        let _ = self.tx.send(Broadcast {
+            from,
+            bytes,
+            kick: None,
+            to: None,
+        });
+    }
+
+    /// Send a message to the clients of this session that may see the keys,editors and the owner
+    pub fn broadcast_to_editors(&self, bytes: Vec<u8>) {
+        let editors = self
+            .participants
+            .iter()
+            .filter(|(_, participant)| participant.role != Role::Viewer)
+            .map(|(client, _)| *client)
+            .collect();
+        let _ = self.tx.send(Broadcast {
+            from: None,
+            bytes,
+            kick: None,
+            to: Some(editors),
+        });
+    }
+
+    /// Replace the editor key,the links with the old one only view the session from now on
+    pub fn rotate_editor_key(&mut self) {
+        self.keys.editor = new_key();
+    }
+
+    /// Send a message to every client of this session,then disconnect `client`
+    pub fn kick(&self, client: usize, bytes: Vec<u8>) {
+        let _ = self.tx.send(Broadcast {
+            from: None,
+            bytes,
+            kick: Some(client),
+            to: None,
+        });
+    }
+
+    /// Serialize the session as a compressed snapshot of its frames and layers
+    fn to_bytes(&self) -> Vec<u8> {
+        snapshot::encode(&self.board)
+    }
+
+    fn from_bytes(data: &[u8]) -> Result<Self, String> {
+        if snapshot::is_snapshot(data) {
+            return Ok(Self::with_board(snapshot::decode(data)?));
+        }
+        // Legacy file: header(width,height) followed by the raw canvas bytes
+        if data.len() < LEGACY_HEADER_LEN {
+            return Err(format!("session file too short: {} bytes", data.len()));
+        }
+        let width = u32::from_le_bytes(data[0..4].try_into().unwrap()) as usize;
+        let height = u32::from_le_bytes(data[4..8].try_into().unwrap()) as usize;
+        check_dimension(width, height)?;
+        let mut session = Self::new(width, height);
+        session.board.first_mut().layers.bottom_mut().canvas.assign_bytes(&data[LEGACY_HEADER_LEN..])?;
+        Ok(session)
+    }
+}
+
+/// All the sessions known by the server, backed by one file per uuid on disk
+pub struct SessionStore {
+    dir: PathBuf,
+    sessions: HashMap<String, Session>,
+    /// Sessions changed since the last flush
+    dirty: HashSet<String>,
+    /// Listings of the public sessions by id,loaded or not
+    public: HashMap<String, SessionListing>,
+}
+
+impl SessionStore {
+    pub fn new(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        let public = read_public(&dir)?;
+        info!("🌐 {} public sessions", public.len());
+        Ok(Self {
+            dir,
+            sessions: HashMap::new(),
+            dirty: HashSet::new(),
+            public,
+        })
+    }
+
+    /// A session in memory
+    pub fn get(&self, id: &str) -> Option<&Session> {
+        self.sessions.get(&normalize_id(id)?)
+    }
+
+    /// Reader of a session that isn't in memory,None if it is or if the id is not a uuid
+    pub fn loader(&self, id: &str) -> Option<SessionLoader> {
+        let id = normalize_id(id)?;
+        if self.sessions.contains_key(&id) {
+            return None;
+        }
+        let path = self.path(&id)?;
+        Some(SessionLoader {
+            id,
+            path,
+            create: None,
+        })
+    }
+
+    /// Keep a session read by a [`SessionLoader`],unless it was loaded in the meantime.
+    /// True if it was kept and created by the loader.
+    pub fn insert_loaded(&mut self, loaded: LoadedSession) -> bool {
+        let LoadedSession {
+            id,
+            session,
+            created,
+            unsaved,
+        } = loaded;
+        if self.sessions.contains_key(&id) {
+            return false;
+        }
+        self.sessions.insert(id.clone(), session);
+        if created || unsaved {
+            self.dirty.insert(id.clone());
+        }
+        // A board replacing a corrupted one keeps its public name but not its size
+        self.update_listing(&id);
+        created
+    }
+
+    /// Listing of every public session,in memory or on disk,the last changed first
+    pub fn published(&self) -> Vec<SessionListing> {
+        let mut listings: Vec<SessionListing> = self.public.values().cloned().collect();
+        listings.sort_by(|a, b| b.modified.cmp(&a.modified));
+        listings
+    }
+
+    pub fn get_mut(&mut self, id: &str) -> Option<&mut Session> {
+        self.sessions.get_mut(&normalize_id(id)?)
+    }
+
+    /// Mark a session as changed,it is written on the next flush
+    pub fn mark_dirty(&mut self, id: &str) {
+        if let Some(id) = normalize_id(id) {
+            if let Some(session) = self.sessions.get_mut(&id) {
+                session.modified = SystemTime::now();
+            }
+            self.update_listing(&id);
+            self.dirty.insert(id);
+        }
+    }
+
+    /// Mark a session as changed without touching its board,like a new chat message or public name
+    pub fn mark_unsaved(&mut self, id: &str) {
+        if let Some(id) = normalize_id(id) {
+            self.update_listing(&id);
+            self.dirty.insert(id);
+        }
+    }
+
+    /// Add,update or remove the public listing of a loaded session
+    fn update_listing(&mut self, id: &str) {
+        let Some(session) = self.sessions.get(id) else {
+            return;
+        };
+        if session.published.is_some() {
+            self.public.insert(id.to_string(), session.listing(id));
+        } else {
+            self.public.remove(id);
+        }
+    }
+
+    /// Serialize every changed session,they are clean until changed again.
+    /// The jobs are written without the lock,a failed one must be marked unsaved again.
+    pub fn take_saves(&mut self) -> Vec<SaveJob> {
+        let mut jobs = Vec::new();
+        let mut failed = Vec::new();
+        for id in std::mem::take(&mut self.dirty) {
+            let Some(session) = self.sessions.get(&id) else {
+                continue;
+            };
+            match self.save_job(&id, session) {
+                Ok(job) => jobs.push(job),
+                Err(e) => {
+                    error!("Failed to serialize session {id}: {e}");
+                    failed.push(id);
+                }
+            }
+        }
+        self.dirty.extend(failed);
+        jobs
+    }
+
+    /// Drop from memory the sessions without clients for `timeout`,once they are saved.
+    /// They are loaded again when a client joins or their png is asked.
+    pub fn evict_idle(&mut self, timeout: Duration) {
+        let now = Instant::now();
+        let mut idle = Vec::new();
+        for (id, session) in &mut self.sessions {
+            if session.tx.receiver_count() > 0 {
+                session.idle_since = None;
+                continue;
+            }
+            let idle_since = *session.idle_since.get_or_insert(now);
+            if now.duration_since(idle_since) >= timeout {
+                idle.push(id.clone());
+            }
+        }
+        for id in idle {
+            // Keep it in memory until a flush saved it
+            if self.dirty.contains(&id) {
+                continue;
+            }
+            self.sessions.remove(&id);
+            info!("💤 Evicted idle session {id}");
+        }
+    }
+
+    /// Files written to save a session
+    fn save_job(&self, id: &str, session: &Session) -> io::Result<SaveJob> {
+        let Some(path) = self.path(id) else {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid session id"));
+        };
+        let mut files = vec![(path.clone(), session.to_bytes())];
+
+        if let Some(palette) = &session.palette {
+            let palette_path = path.with_extension(PALETTE_FORMAT.extension());
+            files.push((palette_path, palette.export(PALETTE_FORMAT).into_bytes()));
+        }
+
+        let keys_path = path.with_extension(KEYS_FILE_EXTENSION);
+        let (width, height) = session.board.dimension();
+        let keys = KeysFile {
+            keys: session.keys.clone(),
+            owner: session.owner_secret.clone(),
+            locked: session.locked,
+            published: session.published.clone(),
+            open: session.open,
+            width: width as u32,
+            height: height as u32,
+        };
+        let json = serde_json::to_vec(&keys).map_err(io::Error::other)?;
+        files.push((keys_path, json));
+
+        if !session.chat.is_empty() {
+            let chat_path = path.with_extension(CHAT_FILE_EXTENSION);
+            let json = serde_json::to_vec(&session.chat).map_err(io::Error::other)?;
+            files.push((chat_path, json));
+        }
+        Ok(SaveJob {
+            id: id.to_string(),
+            files,
+        })
+    }
+
+    /// File path of a session,only uuid are accepted so the id can't escape the directory
+    fn path(&self, id: &str) -> Option<PathBuf> {
+        let id = normalize_id(id)?;
+        Some(self.dir.join(id).with_extension(SESSION_FILE_EXTENSION))
+    }
+}
+
+/// Keys,lock and public name saved next to the session file at `path`.
+/// False if there is no keys file,the session keeps its new keys.
+fn load_keys(path: &Path, session: &mut Session) -> bool {
+    let path = path.with_extension(KEYS_FILE_EXTENSION);
+    let data = match fs::read(&path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return false,
+        Err(e) => {
+            error!("Failed to read keys file {}: {e}", path.display());
+            return true;
+        }
+    };
+    match serde_json::from_slice::<KeysFile>(&data) {
+        Ok(file) => {
+            session.keys = file.keys;
+            session.owner_secret = file.owner;
+            session.locked = file.locked;
+            session.published = file.published;
+            session.open = file.open;
+        }
+        Err(e) => warn!("Corrupted keys file {}: {e}", path.display()),
+    }
+    true
+}
+
+/// Chat saved next to the session file at `path`
+fn load_chat(path: &Path) -> VecDeque<ChatMessage> {
+    let path = path.with_extension(CHAT_FILE_EXTENSION);
+    let Ok(data) = fs::read(&path) else {
+        return VecDeque::new();
+    };
+    serde_json::from_slice(&data)
+        .inspect_err(|e| warn!("Corrupted chat file {}: {e}", path.display()))
+        .unwrap_or_default()
+}
+
+/// Palette saved next to the session file at `path`
+fn load_palette(path: &Path, id: &str) -> Option<Palette> {
+    let path = path.with_extension(PALETTE_FORMAT.extension());
+    let text = fs::read_to_string(&path).ok()?;
+    Palette::parse(PALETTE_FORMAT, &text, id)
+        .inspect_err(|e| warn!("Corrupted palette file {}: {e}", path.display()))
+        .ok()
+}
+
+/// Palette,chat and keys saved next to the session file at `path`.
+/// False if there is no keys file,see [`load_keys`].
+fn load_extras(path: &Path, id: &str, session: &mut Session) -> bool {
+    session.palette = load_palette(path, id);
+    session.chat = load_chat(path);
+    load_keys(path, session)
+}
+
+/// Listings of the public sessions saved in `dir`,from their keys files.
+/// Boards are only read for keys files saved before the size was stored in them,and only their size.
+fn read_public(dir: &Path) -> io::Result<HashMap<String, SessionListing>> {
+    let mut public = HashMap::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|extension| extension != KEYS_FILE_EXTENSION) {
+            continue;
+        }
+        let Some(id) = path.file_stem().and_then(|stem| normalize_id(&stem.to_string_lossy())) else {
+            continue;
+        };
+        let Some(file) = fs::read(&path)
+            .ok()
+            .and_then(|data| serde_json::from_slice::<KeysFile>(&data).ok())
+        else {
+            warn!("Unreadable keys file {}", path.display());
+            continue;
+        };
+        if file.published.is_none() {
+            continue;
+        }
+        let session_path = path.with_extension(SESSION_FILE_EXTENSION);
+        let (width, height) = if file.width > 0 && file.height > 0 {
+            (file.width, file.height)
+        } else {
+            match fs::read(&session_path)
+                .map_err(|e| e.to_string())
+                .and_then(|data| snapshot::dimension(&data))
+            {
+                Ok((width, height)) => (width as u32, height as u32),
+                Err(e) => {
+                    warn!("Unlisted public session {id}: {e}");
+                    continue;
+                }
+            }
+        };
+        let listing = SessionListing {
+            id: id.clone(),
+            name: file.published,
+            width,
+            height,
+            modified: unix_millis(file_modified(&session_path)),
+        };
+        public.insert(id, listing);
+    }
+    Ok(public)
+}
+
+/// Last write of a file,now if it can't be read
+fn file_modified(path: &Path) -> SystemTime {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .unwrap_or_else(|_| SystemTime::now())
+}
+
+/// Milliseconds since the unix epoch
+pub fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// Random capability key
+fn new_key() -> String {
+    Uuid::new_v4().simple().to_string()
+}
+
+/// Canonical form of a session id,None if it is not a uuid
+pub fn normalize_id(id: &str) -> Option<String> {
+    Uuid::parse_str(id).ok().map(|id| id.to_string())
+}
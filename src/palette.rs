@@ -0,0 +1,241 @@
+//! Color palettes shared by a session,and the palette file formats they are imported from.
+//!
+//! Supported formats:
+//! - GIMP palette (`.gpl`)
+//! - one `rrggbb` per line (`.hex`),as downloaded from Lospec
+//! - Lospec JSON (`.json`),`{"name": ..., "colors": ["rrggbb", ...]}`
+use serde::{Deserialize, Serialize};
+use shared::PixelColor;
+
+/// Palettes bigger than this are rejected,they would not fit in the color popup anyway
+pub const MAX_PALETTE_COLORS: usize = 256;
+/// Longest palette name,in characters
+pub const MAX_PALETTE_NAME: usize = 64;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Palette {
+    pub name: String,
+    /// rgba of every swatch,in order
+    pub colors: Vec<[u8; 4]>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PaletteFormat {
+    Gpl,
+    Hex,
+    LospecJson,
+}
+
+impl PaletteFormat {
+    pub const ALL: [PaletteFormat; 3] = [PaletteFormat::Gpl, PaletteFormat::Hex, PaletteFormat::LospecJson];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            PaletteFormat::Gpl => "gpl",
+            PaletteFormat::Hex => "hex",
+            PaletteFormat::LospecJson => "json",
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            PaletteFormat::Gpl => "GIMP (.gpl)",
+            PaletteFormat::Hex => "Hex (.hex)",
+            PaletteFormat::LospecJson => "Lospec (.json)",
+        }
+    }
+    /// Guess the format from the extension of a file name
+    pub fn from_file_name(file_name: &str) -> Option<Self> {
+        let (_, extension) = file_name.rsplit_once('.')?;
+        Self::ALL
+            .into_iter()
+            .find(|format| format.extension().eq_ignore_ascii_case(extension))
+    }
+}
+
+/// Lospec JSON layout,only the fields we use
+#[derive(Serialize, Deserialize)]
+struct LospecPalette {
+    #[serde(default)]
+    name: String,
+    colors: Vec<String>,
+}
+
+impl Default for Palette {
+    /// The eight basic colors the toolbar always had
+    fn default() -> Self {
+        Self::new(
+            "Basic",
+            [
+                PixelColor::BLACK,
+                PixelColor::RED,
+                PixelColor::GREEN,
+                PixelColor::BLUE,
+                PixelColor::YELLOW,
+                PixelColor::MAGENTA,
+                PixelColor::CYAN,
+                PixelColor::WHITE,
+            ]
+            .iter()
+            .map(PixelColor::to_rgba)
+            .collect(),
+        )
+    }
+}
+
+impl Palette {
+    pub fn new(name: impl Into<String>, colors: Vec<[u8; 4]>) -> Self {
+        Self {
+            name: name.into(),
+            colors,
+        }
+    }
+    /// Keep the name on a single line,without the surrounding spaces and cut to [`MAX_PALETTE_NAME`].
+    /// It is written in the header of palette files.
+    pub fn clip_name(&mut self) {
+        let name: String = self
+            .name
+            .chars()
+            .map(|c| if c.is_control() { ' ' } else { c })
+            .collect();
+        let name: String = name.trim().chars().take(MAX_PALETTE_NAME).collect();
+        self.name = name.trim_end().to_string();
+    }
+    pub fn pixel_colors(&self) -> impl Iterator<Item = PixelColor> + '_ {
+        self.colors
+            .iter()
+            .map(|&[r, g, b, a]| PixelColor::new(r, g, b, a))
+    }
+    /// Add a color at the end,nothing happens if it is already there or the palette is full
+    pub fn add(&mut self, color: PixelColor) {
+        let color = color.to_rgba();
+        if !self.colors.contains(&color) && self.colors.len() < MAX_PALETTE_COLORS {
+            self.colors.push(color);
+        }
+    }
+    /// Remove a color,the last one is kept so a palette never ends up empty
+    pub fn remove(&mut self, index: usize) {
+        if index < self.colors.len() && self.colors.len() > 1 {
+            self.colors.remove(index);
+        }
+    }
+
+    /// Parse a palette file,`name` is used when the file doesn't name the palette
+    pub fn parse(format: PaletteFormat, text: &str, name: &str) -> Result<Self, String> {
+        let palette = match format {
+            PaletteFormat::Gpl => parse_gpl(text, name)?,
+            PaletteFormat::Hex => Self::new(
+                name,
+                text.lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty())
+                    .map(|line| parse_hex(line).ok_or(format!("invalid hex color: {line}")))
+                    .collect::<Result<_, _>>()?,
+            ),
+            PaletteFormat::LospecJson => {
+                let lospec: LospecPalette = serde_json::from_str(text)
+                    .map_err(|e| format!("invalid lospec palette: {e}"))?;
+                Self::new(
+                    if lospec.name.is_empty() { name.to_string() } else { lospec.name },
+                    lospec
+                        .colors
+                        .iter()
+                        .map(|hex| parse_hex(hex).ok_or(format!("invalid hex color: {hex}")))
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+        };
+        if palette.colors.is_empty() {
+            return Err("the palette has no colors".to_string());
+        }
+        if palette.colors.len() > MAX_PALETTE_COLORS {
+            return Err(format!(
+                "the palette has {} colors,at most {MAX_PALETTE_COLORS} are supported",
+                palette.colors.len()
+            ));
+        }
+        Ok(palette)
+    }
+
+    /// Write the palette as a file of the given format.
+    /// The formats only store rgb,alpha is dropped.
+    pub fn export(&self, format: PaletteFormat) -> String {
+        match format {
+            PaletteFormat::Gpl => {
+                let mut text = format!("GIMP Palette\nName: {}\nColumns: 8\n#\n", self.name);
+                for color @ [r, g, b, _] in &self.colors {
+                    text.push_str(&format!("{r:3} {g:3} {b:3}\t{}\n", rgb_hex(color)));
+                }
+                text
+            }
+            PaletteFormat::Hex => self
+                .colors
+                .iter()
+                .map(|color| rgb_hex(color) + "\n")
+                .collect(),
+            PaletteFormat::LospecJson => {
+                let lospec = LospecPalette {
+                    name: self.name.clone(),
+                    colors: self.colors.iter().map(rgb_hex).collect(),
+                };
+                serde_json::to_string_pretty(&lospec).expect("a palette is always serializable")
+            }
+        }
+    }
+}
+
+fn parse_gpl(text: &str, name: &str) -> Result<Palette, String> {
+    let mut lines = text.lines().map(str::trim);
+    if lines.next() != Some("GIMP Palette") {
+        return Err("missing GIMP Palette header".to_string());
+    }
+    let mut palette = Palette::new(name, Vec::new());
+    for line in lines {
+        if let Some(palette_name) = line.strip_prefix("Name:") {
+            palette.name = palette_name.trim().to_string();
+            continue;
+        }
+        if line.is_empty() || line.starts_with('#') || line.starts_with("Columns:") {
+            continue;
+        }
+        // "r g b name",the name is optional
+        let channels: Vec<u8> = line
+            .split_whitespace()
+            .take(3)
+            .map(|channel| channel.parse().map_err(|_| format!("invalid palette line: {line}")))
+            .collect::<Result<_, _>>()?;
+        let [r, g, b] = channels[..] else {
+            return Err(format!("invalid palette line: {line}"));
+        };
+        palette.colors.push([r, g, b, 255]);
+    }
+    Ok(palette)
+}
+
+/// Parse `#rgb`,`#rrggbb` or `#rrggbbaa`,the `#` is optional
+pub fn parse_hex(hex: &str) -> Option<[u8; 4]> {
+    let hex = hex.trim().trim_start_matches('#');
+    if !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize, len: usize| u8::from_str_radix(&hex[i * len..(i + 1) * len], 16).ok();
+    match hex.len() {
+        3 => Some([channel(0, 1)? * 17, channel(1, 1)? * 17, channel(2, 1)? * 17, 255]),
+        6 => Some([channel(0, 2)?, channel(1, 2)?, channel(2, 2)?, 255]),
+        8 => Some([channel(0, 2)?, channel(1, 2)?, channel(2, 2)?, channel(3, 2)?]),
+        _ => None,
+    }
+}
+
+/// `#rrggbb`,or `#rrggbbaa` when the color isn't opaque
+pub fn to_hex(&[r, g, b, a]: &[u8; 4]) -> String {
+    if a == 255 {
+        format!("#{r:02x}{g:02x}{b:02x}")
+    } else {
+        format!("#{r:02x}{g:02x}{b:02x}{a:02x}")
+    }
+}
+
+/// `rrggbb` without `#`,as palette files store it
+fn rgb_hex(&[r, g, b, _]: &[u8; 4]) -> String {
+    format!("{r:02x}{g:02x}{b:02x}")
+}
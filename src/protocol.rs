@@ -0,0 +1,349 @@
+//! Messages passed on the session websocket,between the drawing page and the server.
+use bincode::config;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use shared::{DataPass, DrawingPixelCanvas, PixelColor};
+
+use crate::{
+    board::{Anchor, FrameOp},
+    layers::LayerOp,
+    palette::Palette,
+};
+
+/// Longest display name,in characters
+pub const MAX_NAME_LEN: usize = 32;
+/// Longest chat message,in characters
+pub const MAX_CHAT_LEN: usize = 500;
+/// Chat messages kept by a session,older ones are dropped
+pub const MAX_CHAT_HISTORY: usize = 200;
+/// Longest tool name of a presence,in characters
+pub const MAX_TOOL_LEN: usize = 16;
+
+/// Display name without the surrounding spaces,cut to [`MAX_NAME_LEN`].
+/// None if nothing is left.
+pub fn clean_name(name: &str) -> Option<String> {
+    clean_text(name, MAX_NAME_LEN)
+}
+
+/// Chat message without the surrounding spaces,cut to [`MAX_CHAT_LEN`].
+/// None if nothing is left.
+pub fn clean_chat(text: &str) -> Option<String> {
+    clean_text(text, MAX_CHAT_LEN)
+}
+
+fn clean_text(text: &str, max_len: usize) -> Option<String> {
+    let text: String = text.trim().chars().take(max_len).collect();
+    let text = text.trim_end();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// Query parameter of the page url holding the capability key of a session
+pub const KEY_QUERY: &str = "key";
+/// Path of the json list of the public sessions,`<path>/<uuid>` describes a single session
+pub const SESSIONS_PATH: &str = "/sessions";
+
+/// A session as listed by the server
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SessionListing {
+    pub id: String,
+    /// Name the session is listed under,None if it isn't public
+    pub name: Option<String>,
+    pub width: u32,
+    pub height: u32,
+    /// Last change,in milliseconds since the unix epoch
+    pub modified: u64,
+}
+
+/// What a client may do in a session
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Role {
+    /// Sees the board,its cursor and chat,but can't change the board
+    #[default]
+    Viewer,
+    Editor,
+    /// Created the session,edits it even while it is locked and can use the [`OwnerAction`]s
+    Owner,
+}
+
+impl Role {
+    /// Check if the role may change the board,only the owner may while the session is locked
+    pub fn can_edit(self, locked: bool) -> bool {
+        match self {
+            Role::Viewer => false,
+            Role::Editor => !locked,
+            Role::Owner => true,
+        }
+    }
+}
+
+/// Change of the whole session,only its owner may make it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum OwnerAction {
+    /// Stop or allow the editors changing the board
+    SetLocked { locked: bool },
+    /// Disconnect a participant
+    Kick { client: u64 },
+    /// Erase every layer of every frame
+    Clear,
+    /// Change the size of the board,the pixels stay at the anchor
+    Resize {
+        width: u32,
+        height: u32,
+        anchor: Anchor,
+    },
+    /// Keep only a rectangle of the board,its top left corner becomes the board's
+    Crop {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    },
+    /// List the session publicly under a name,or stop listing it with None
+    Publish { name: Option<String> },
+}
+
+/// Capability keys of a session,the link of the page with one of them gives its role.
+/// Only editors are told the keys.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SessionKeys {
+    pub editor: String,
+    pub viewer: String,
+}
+
+/// Message of the session chat
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ChatMessage {
+    /// Display name of the author when it was sent
+    pub name: String,
+    pub text: String,
+    /// Milliseconds since the unix epoch,given by the server
+    pub sent_at: u64,
+}
+
+/// Horizontal run of changed pixels,starting at (x,y)
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PixelRun {
+    pub x: u32,
+    pub y: u32,
+    /// rgba of every pixel in the run
+    pub colors: Vec<[u8; 4]>,
+}
+
+/// Pixels changed since the last delta.
+/// From the client `seq` count the deltas it sent,from the server it is the session revision.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CanvasDelta {
+    pub seq: u64,
+    /// Id of the frame the pixels were drawn on
+    pub frame: u32,
+    /// Id of the layer the pixels were drawn on
+    pub layer: u32,
+    pub runs: Vec<PixelRun>,
+    /// Size of the board the pixels were drawn on,they don't belong to a board of another size
+    pub width: u32,
+    pub height: u32,
+}
+
+impl CanvasDelta {
+    /// Collect the painted pixels of a drawing layer,None if nothing was drawn
+    pub fn from_drawing(
+        seq: u64,
+        frame: u32,
+        layer: u32,
+        drawing: &DrawingPixelCanvas,
+    ) -> Option<Self> {
+        let (width, height) = drawing.dimension();
+        let mut runs = Vec::new();
+        for y in 0..height {
+            let mut run: Option<PixelRun> = None;
+            for x in 0..width {
+                let color = drawing.get_pixel(x, y);
+                if color == PixelColor::TRANSPARENT {
+                    runs.extend(run.take());
+                    continue;
+                }
+                run.get_or_insert_with(|| PixelRun {
+                    x: x as u32,
+                    y: y as u32,
+                    colors: Vec::new(),
+                })
+                .colors
+                .push(color.to_rgba());
+            }
+            runs.extend(run);
+        }
+        (!runs.is_empty()).then_some(Self {
+            seq,
+            frame,
+            layer,
+            runs,
+            width: width as u32,
+            height: height as u32,
+        })
+    }
+    /// Check if the delta was drawn on a board of this size
+    pub fn fits(&self, dimension: (usize, usize)) -> bool {
+        (self.width as usize, self.height as usize) == dimension
+    }
+    /// Number of pixels carried by the delta
+    pub fn len(&self) -> usize {
+        self.runs.iter().map(|run| run.colors.len()).sum()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.runs.iter().all(|run| run.colors.is_empty())
+    }
+    /// Drawing layer holding the delta,to be merged on top of a canvas
+    pub fn to_drawing(&self, width: usize, height: usize) -> DrawingPixelCanvas {
+        let mut drawing = DrawingPixelCanvas::new(width, height);
+        for run in &self.runs {
+            for (offset, [r, g, b, a]) in run.colors.iter().copied().enumerate() {
+                drawing.draw_pixel_ignore(
+                    run.x as usize + offset,
+                    run.y as usize,
+                    PixelColor::new(r, g, b, a),
+                );
+            }
+        }
+        drawing
+    }
+    /// Merge the delta on top of a canvas
+    pub fn apply(&self, canvas: &mut DrawingPixelCanvas) {
+        let (width, height) = canvas.dimension();
+        canvas.merge_top(&self.to_drawing(width, height));
+    }
+}
+
+/// Where a participant points and with which tool,shown to the others as a cursor
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Presence {
+    /// Hovered cell,None when the mouse is off the grid
+    pub cell: Option<(u32, u32)>,
+    /// Name of the selected tool
+    pub tool: String,
+}
+
+impl Presence {
+    /// Tool name without the surrounding spaces,cut to [`MAX_TOOL_LEN`]
+    pub fn clipped(mut self) -> Self {
+        self.tool = clean_text(&self.tool, MAX_TOOL_LEN).unwrap_or_default();
+        self
+    }
+}
+
+/// Message sent from the drawing page to the server
+#[derive(Serialize, Deserialize)]
+pub enum ClientMessage {
+    /// Session setup and full canvas messages
+    Pass(DataPass),
+    /// Pixels drawn since the last delta
+    Delta(CanvasDelta),
+    /// Replace the palette shared by the session
+    Palette(Palette),
+    /// Change the layer structure,the server answers with the op to everyone
+    Layer(LayerOp),
+    /// Change the animation frames,the server answers with the op to everyone
+    Frame(FrameOp),
+    /// Hovered cell and tool changed
+    Presence(Presence),
+    /// Display name of the user,sent after joining and when it changes
+    Name(String),
+    /// Post a message on the session chat
+    Chat(String),
+    /// Capability key of the page link,sent before joining,see [`Role`]
+    Key(String),
+    /// Secret given to the owner of the session,sent before joining
+    OwnerSecret(String),
+    /// Change the session as its owner
+    Owner(OwnerAction),
+}
+
+/// Message sent from the server to the drawing page
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum ServerMessage {
+    /// Every layer of the session as a compressed snapshot,see [`crate::snapshot`]
+    Snapshot(Vec<u8>),
+    /// Every delta of the client up to `seq` is merged in the session
+    Ack { seq: u64 },
+    /// Delta merged by another client of the session
+    Delta(CanvasDelta),
+    /// Palette shared by the session,sent on join and whenever a client changes it
+    Palette(Palette),
+    /// Layer change accepted by the server,new layers carry their id
+    Layer(LayerOp),
+    /// Frame change accepted by the server,new frames carry their id
+    Frame(FrameOp),
+    /// Id the server gave to this client and its role,sent first on join.
+    /// Editors get the keys of the session links,the owner its secret.
+    Welcome {
+        client: u64,
+        role: Role,
+        keys: Option<SessionKeys>,
+        owner_secret: Option<String>,
+    },
+    /// Another client joined the session or changed its name,sent on join for everyone already there
+    Joined { client: u64, name: String },
+    /// Cursor of another client,sent on join for everyone already there and when it moves
+    Presence { client: u64, presence: Presence },
+    /// Another client left the session
+    Left { client: u64 },
+    /// Every message of the session chat,oldest first,sent on join
+    ChatHistory(Vec<ChatMessage>),
+    /// New message of the session chat,sent to everyone,author included
+    Chat(ChatMessage),
+    /// Only the owner may change the board,sent on join and when the owner changes it
+    Locked(bool),
+    /// The owner removed a client,the connection of that client is closed after it
+    Kicked { client: u64 },
+    /// Name the session is publicly listed under,None if it isn't,sent on join and when the owner changes it
+    Published(Option<String>),
+    /// New keys of the session links,sent to editors and the owner when kicking an editor changed them
+    Keys(SessionKeys),
+    /// Last message of the initial sync,sent on join and resync
+    Synced,
+}
+
+impl ClientMessage {
+    /// Check if the message changes the board,which viewers may not do.
+    /// Owner actions are checked on their own.
+    pub fn is_write(&self) -> bool {
+        match self {
+            ClientMessage::Key(_)
+            | ClientMessage::OwnerSecret(_)
+            | ClientMessage::Pass(DataPass::Whid { .. }) => false,
+            ClientMessage::Pass(DataPass::Canvas { .. })
+            | ClientMessage::Delta(_)
+            | ClientMessage::Palette(_)
+            | ClientMessage::Layer(_)
+            | ClientMessage::Frame(_) => true,
+            ClientMessage::Presence(_)
+            | ClientMessage::Name(_)
+            | ClientMessage::Chat(_)
+            | ClientMessage::Owner(_) => false,
+        }
+    }
+    pub fn to_bytes(&self) -> Vec<u8> {
+        encode(self)
+    }
+    pub fn from_bytes(data: &[u8]) -> Result<Self, String> {
+        decode(data)
+    }
+}
+
+impl ServerMessage {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        encode(self)
+    }
+    pub fn from_bytes(data: &[u8]) -> Result<Self, String> {
+        decode(data)
+    }
+}
+
+fn encode(message: &impl Serialize) -> Vec<u8> {
+    bincode::serde::encode_to_vec(message, config::standard())
+        .expect("protocol messages are always serializable")
+}
+
+fn decode<T: DeserializeOwned>(data: &[u8]) -> Result<T, String> {
+    bincode::serde::decode_from_slice(data, config::standard())
+        .map(|(message, _)| message)
+        .map_err(|e| format!("invalid message: {e}"))
+}
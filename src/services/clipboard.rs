@@ -0,0 +1,62 @@
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::js_sys;
+
+const PNG_MIME: &str = "image/png";
+
+fn clipboard() -> Result<web_sys::Clipboard, String> {
+    let window = web_sys::window().ok_or("no window")?;
+    Ok(window.navigator().clipboard())
+}
+
+fn js_error(err: JsValue) -> String {
+    format!("clipboard error: {err:?}")
+}
+
+/// Put a png image on the system clipboard
+pub async fn write_png(bytes: &[u8]) -> Result<(), String> {
+    let array = js_sys::Uint8Array::from(bytes);
+    let options = web_sys::BlobPropertyBag::new();
+    options.set_type(PNG_MIME);
+    let blob =
+        web_sys::Blob::new_with_u8_array_sequence_and_options(&js_sys::Array::of1(&array), &options)
+            .map_err(js_error)?;
+
+    let record = js_sys::Object::new();
+    js_sys::Reflect::set(&record, &PNG_MIME.into(), &blob).map_err(js_error)?;
+    let item =
+        web_sys::ClipboardItem::new_with_record_from_str_to_blob_promise(&record).map_err(js_error)?;
+    JsFuture::from(clipboard()?.write(&js_sys::Array::of1(&item)))
+        .await
+        .map_err(js_error)?;
+    Ok(())
+}
+
+/// Read the first png image of the system clipboard,None if it holds no image
+pub async fn read_png() -> Result<Option<Vec<u8>>, String> {
+    let items = JsFuture::from(clipboard()?.read()).await.map_err(js_error)?;
+    let items: js_sys::Array = items.dyn_into().map_err(js_error)?;
+    for item in items.iter() {
+        let item: web_sys::ClipboardItem = item.dyn_into().map_err(js_error)?;
+        if !item.types().includes(&PNG_MIME.into(), 0) {
+            continue;
+        }
+        let blob = JsFuture::from(item.get_type(PNG_MIME))
+            .await
+            .map_err(js_error)?;
+        let blob: web_sys::Blob = blob.dyn_into().map_err(js_error)?;
+        let buffer = JsFuture::from(blob.array_buffer())
+            .await
+            .map_err(js_error)?;
+        return Ok(Some(js_sys::Uint8Array::new(&buffer).to_vec()));
+    }
+    Ok(None)
+}
+
+/// Put text on the system clipboard
+pub async fn write_text(text: &str) -> Result<(), String> {
+    JsFuture::from(clipboard()?.write_text(text))
+        .await
+        .map_err(js_error)?;
+    Ok(())
+}
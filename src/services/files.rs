@@ -0,0 +1,47 @@
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::js_sys;
+
+fn js_error(err: JsValue) -> String {
+    format!("file error: {err:?}")
+}
+
+/// Save bytes as a file through a temporary download link
+pub fn download(bytes: &[u8], file_name: &str) -> Result<(), String> {
+    let array = js_sys::Uint8Array::from(bytes);
+    let blob = web_sys::Blob::new_with_u8_array_sequence(&js_sys::Array::of1(&array))
+        .map_err(js_error)?;
+    let url = web_sys::Url::create_object_url_with_blob(&blob).map_err(js_error)?;
+
+    let document = web_sys::window()
+        .and_then(|window| window.document())
+        .ok_or("no document")?;
+    let a = document
+        .create_element("a")
+        .map_err(js_error)?
+        .dyn_into::<web_sys::HtmlAnchorElement>()
+        .map_err(js_error)?;
+    a.set_href(&url);
+    a.set_download(file_name);
+    a.click();
+
+    web_sys::Url::revoke_object_url(&url).map_err(js_error)
+}
+
+/// First file picked in an `<input type="file">`
+pub fn picked_file(input: &web_sys::HtmlInputElement) -> Option<web_sys::File> {
+    input.files()?.get(0)
+}
+
+pub async fn read_text(file: &web_sys::File) -> Result<String, String> {
+    JsFuture::from(file.text())
+        .await
+        .map_err(js_error)?
+        .as_string()
+        .ok_or(format!("{} is not a text file", file.name()))
+}
+
+pub async fn read_bytes(file: &web_sys::File) -> Result<Vec<u8>, String> {
+    let buffer = JsFuture::from(file.array_buffer()).await.map_err(js_error)?;
+    Ok(js_sys::Uint8Array::new(&buffer).to_vec())
+}
@@ -0,0 +1,21 @@
+//! Links of the session pages,carrying the capability key of a role.
+use frontend::protocol::KEY_QUERY;
+use wasm_bindgen::JsValue;
+
+/// Link to the page of a session,the key gives the role of whoever opens it
+pub fn session_link(session_id: &str, key: &str) -> String {
+    let origin = web_sys::window()
+        .and_then(|window| window.location().origin().ok())
+        .unwrap_or_default();
+    format!("{origin}/drawing/{session_id}?{KEY_QUERY}={key}")
+}
+
+/// Change the url of the page without loading it,so a reload keeps the new url
+pub fn replace_url(url: &str) -> Result<(), String> {
+    web_sys::window()
+        .ok_or("no window")?
+        .history()
+        .map_err(|err| format!("history error: {err:?}"))?
+        .replace_state_with_url(&JsValue::NULL, "", Some(url))
+        .map_err(|err| format!("history error: {err:?}"))
+}
@@ -0,0 +1,6 @@
+pub mod clipboard;
+pub mod files;
+pub mod links;
+pub mod sessions;
+pub mod storage;
+pub mod websocket;
@@ -0,0 +1,51 @@
+//! Sessions listed by the server,and their thumbnails.
+use frontend::protocol::{SESSIONS_PATH, SessionListing};
+use serde::de::DeserializeOwned;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+
+/// Longest side of a thumbnail,in pixels
+const THUMBNAIL_SIDE: usize = 128;
+
+fn js_error(err: JsValue) -> String {
+    format!("fetch error: {err:?}")
+}
+
+/// Get and parse a json document of the server,None if it answers 404
+async fn fetch_json<T: DeserializeOwned>(path: &str) -> Result<Option<T>, String> {
+    let window = web_sys::window().ok_or("no window")?;
+    let response = JsFuture::from(window.fetch_with_str(path))
+        .await
+        .map_err(js_error)?;
+    let response: web_sys::Response = response.dyn_into().map_err(js_error)?;
+    if response.status() == 404 {
+        return Ok(None);
+    }
+    if !response.ok() {
+        return Err(format!("{path} answered {}", response.status()));
+    }
+    let text = JsFuture::from(response.text().map_err(js_error)?)
+        .await
+        .map_err(js_error)?
+        .as_string()
+        .unwrap_or_default();
+    serde_json::from_str(&text)
+        .map(Some)
+        .map_err(|e| format!("invalid json from {path}: {e}"))
+}
+
+/// Sessions the server lists publicly,the last changed first
+pub async fn public_sessions() -> Result<Vec<SessionListing>, String> {
+    Ok(fetch_json(SESSIONS_PATH).await?.unwrap_or_default())
+}
+
+/// Size and last change of a session,None if the server doesn't have it
+pub async fn session_listing(id: &str) -> Result<Option<SessionListing>, String> {
+    fetch_json(&format!("{SESSIONS_PATH}/{id}")).await
+}
+
+/// Url of the png of a session,scaled up so small boards aren't tiny
+pub fn thumbnail_url(id: &str, width: usize, height: usize) -> String {
+    let scale = (THUMBNAIL_SIDE / width.max(height).max(1)).max(1);
+    format!("/canvas/{id}.png?scale={scale}")
+}
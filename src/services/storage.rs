@@ -0,0 +1,26 @@
+//! Values the browser remembers across visits,in localStorage.
+
+/// Key of the display name of the user
+pub const DISPLAY_NAME_KEY: &str = "display_name";
+/// Key of the sessions opened in this browser,as json
+pub const RECENT_SESSIONS_KEY: &str = "recent_sessions";
+
+fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+pub fn get(key: &str) -> Option<String> {
+    local_storage()?.get_item(key).ok()?
+}
+
+pub fn set(key: &str, value: &str) -> Result<(), String> {
+    local_storage()
+        .ok_or("localStorage is not available")?
+        .set_item(key, value)
+        .map_err(|err| format!("Failed to store {key}: {err:?}"))
+}
+
+/// Key of the owner secret of a session,only the browser that created it has one
+pub fn owner_secret_key(session_id: &str) -> String {
+    format!("owner_secret:{session_id}")
+}
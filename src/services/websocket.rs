@@ -0,0 +1,37 @@
+/// Query parameter overriding the websocket endpoint,e.g. `?ws=wss://example.com/ws`
+pub const ENDPOINT_QUERY: &str = "ws";
+/// Build-time override of the endpoint,e.g. `CANVAS_WS_ENDPOINT=wss://example.com/ws trunk build`
+const BUILD_ENDPOINT: Option<&str> = option_env!("CANVAS_WS_ENDPOINT");
+/// Path the server serves the session websockets on,followed by the session uuid
+pub const DEFAULT_WS_PATH: &str = "/ws";
+
+/// Url of the websocket of a session,`<endpoint>/<uuid>`.
+///
+/// The endpoint is the query override if there is one,then the build-time override,
+/// and otherwise the page's own host on `/ws`,with `wss` when the page is served over https.
+/// An endpoint starting with `/` is a path on the page's host,useful behind a reverse proxy.
+pub fn websocket_url(session_id: &str, query_override: Option<String>) -> String {
+    let endpoint = query_override
+        .or_else(|| BUILD_ENDPOINT.map(str::to_string))
+        .filter(|endpoint| !endpoint.is_empty())
+        .unwrap_or_else(|| DEFAULT_WS_PATH.to_string());
+    let endpoint = if endpoint.starts_with('/') {
+        format!("{}{endpoint}", page_origin())
+    } else {
+        endpoint
+    };
+    format!("{}/{session_id}", endpoint.trim_end_matches('/'))
+}
+
+/// `ws://host` or `wss://host` of the current page
+fn page_origin() -> String {
+    let location = web_sys::window().expect("no window").location();
+    let scheme = match location.protocol().as_deref() {
+        Ok("https:") => "wss",
+        _ => "ws",
+    };
+    let host = location
+        .host()
+        .unwrap_or_else(|_| "127.0.0.1:8080".to_string());
+    format!("{scheme}://{host}")
+}
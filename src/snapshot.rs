@@ -0,0 +1,479 @@
+//! Compressed full-state format of a canvas,used for the initial sync and the saved sessions.
+//!
+//! Layout: `MAGIC` + version byte + bincode encoded [`SnapshotBody`].
+//! Pixels of every layer of every frame are run-length encoded row by row,so a mostly empty board is a handful of runs.
+//! Version 2 snapshots,from before frames,are read as a single frame
+//! and version 1 snapshots,from before layers,as a single background layer.
+use bincode::config;
+use serde::{Deserialize, Serialize};
+use shared::{DrawingPixelCanvas, PixelColor};
+
+use crate::board::{Board, Frame, check_dimension};
+use crate::layers::{Layer, LayerInfo, LayerStack};
+
+/// First bytes of every snapshot,tells a snapshot apart from raw canvas bytes
+pub const MAGIC: &[u8; 3] = b"PXS";
+/// Version of the layout written by [`encode`]
+pub const VERSION: u8 = 3;
+/// Layout with layers but a single frame,still accepted by [`decode`]
+const VERSION_SINGLE_FRAME: u8 = 2;
+/// Layout with a single canvas,still accepted by [`decode`]
+const VERSION_SINGLE_CANVAS: u8 = 1;
+const HEADER_LEN: usize = MAGIC.len() + 1;
+
+/// `count` consecutive pixels of the same rgba color
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+struct Run {
+    count: u32,
+    color: [u8; 4],
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct FrameBody {
+    id: u32,
+    duration_ms: u32,
+    /// Runs of every layer,in the order of `SnapshotBody::layers`
+    layers: Vec<Vec<Run>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct SnapshotBody {
+    width: u32,
+    height: u32,
+    next_layer_id: u32,
+    next_frame_id: u32,
+    /// Layers shared by every frame,bottom first
+    layers: Vec<LayerInfo>,
+    /// Frames in playback order
+    frames: Vec<FrameBody>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct LayerBody {
+    info: LayerInfo,
+    runs: Vec<Run>,
+}
+
+/// Version 2 body
+#[derive(Serialize, Deserialize, Debug)]
+struct SingleFrameBody {
+    width: u32,
+    height: u32,
+    next_id: u32,
+    /// Bottom layer first
+    layers: Vec<LayerBody>,
+}
+
+/// Version 1 body
+#[derive(Serialize, Deserialize, Debug)]
+struct SingleCanvasBody {
+    width: u32,
+    height: u32,
+    runs: Vec<Run>,
+}
+
+/// Start of every body version
+#[derive(Deserialize, Debug)]
+struct DimensionBody {
+    width: u32,
+    height: u32,
+}
+
+/// Check if the bytes start with a snapshot header
+pub fn is_snapshot(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+pub fn encode(board: &Board) -> Vec<u8> {
+    let (width, height) = board.dimension();
+    let first = &board.first().layers;
+    let body = SnapshotBody {
+        width: width as u32,
+        height: height as u32,
+        next_layer_id: first.next_id(),
+        next_frame_id: board.next_id(),
+        layers: first.layers().iter().map(|layer| layer.info.clone()).collect(),
+        frames: board
+            .frames()
+            .iter()
+            .map(|frame| FrameBody {
+                id: frame.id,
+                duration_ms: frame.duration_ms,
+                layers: frame
+                    .layers
+                    .layers()
+                    .iter()
+                    .map(|layer| encode_runs(&layer.canvas))
+                    .collect(),
+            })
+            .collect(),
+    };
+
+    let mut bytes = Vec::with_capacity(HEADER_LEN);
+    bytes.extend_from_slice(MAGIC);
+    bytes.push(VERSION);
+    bincode::serde::encode_into_std_write(&body, &mut bytes, config::standard())
+        .expect("writing to a vec never fails");
+    bytes
+}
+
+/// Width and height of a snapshot,read from the start of its body without decoding the pixels
+pub fn dimension(data: &[u8]) -> Result<(usize, usize), String> {
+    if !is_snapshot(data) || data.len() < HEADER_LEN {
+        return Err("not a canvas snapshot".to_string());
+    }
+    match data[MAGIC.len()] {
+        VERSION | VERSION_SINGLE_FRAME | VERSION_SINGLE_CANVAS => {
+            let body: DimensionBody = decode_body(&data[HEADER_LEN..])?;
+            Ok((body.width as usize, body.height as usize))
+        }
+        version => Err(format!("unsupported snapshot version {version}")),
+    }
+}
+
+pub fn decode(data: &[u8]) -> Result<Board, String> {
+    if !is_snapshot(data) || data.len() < HEADER_LEN {
+        return Err("not a canvas snapshot".to_string());
+    }
+    let version = data[MAGIC.len()];
+    let data = &data[HEADER_LEN..];
+    match version {
+        VERSION => {
+            let body: SnapshotBody = decode_body(data)?;
+            let (width, height) = (body.width as usize, body.height as usize);
+            check_dimension(width, height)?;
+            let frames = body
+                .frames
+                .into_iter()
+                .map(|frame| {
+                    if frame.layers.len() != body.layers.len() {
+                        return Err(format!("frame {} doesn't have every layer", frame.id));
+                    }
+                    let layers = body
+                        .layers
+                        .iter()
+                        .zip(frame.layers)
+                        .map(|(info, runs)| {
+                            Ok(Layer {
+                                info: info.clone(),
+                                canvas: decode_runs(width, height, runs)?,
+                            })
+                        })
+                        .collect::<Result<_, String>>()?;
+                    Ok(Frame {
+                        id: frame.id,
+                        duration_ms: frame.duration_ms,
+                        layers: LayerStack::from_layers(width, height, layers, body.next_layer_id)?,
+                    })
+                })
+                .collect::<Result<_, String>>()?;
+            Board::from_frames(frames, body.next_frame_id)
+        }
+        VERSION_SINGLE_FRAME => {
+            let body: SingleFrameBody = decode_body(data)?;
+            let (width, height) = (body.width as usize, body.height as usize);
+            check_dimension(width, height)?;
+            let layers = body
+                .layers
+                .into_iter()
+                .map(|layer| {
+                    Ok(Layer {
+                        canvas: decode_runs(width, height, layer.runs)?,
+                        info: layer.info,
+                    })
+                })
+                .collect::<Result<_, String>>()?;
+            let layers = LayerStack::from_layers(width, height, layers, body.next_id)?;
+            Ok(Board::from_layers(layers))
+        }
+        VERSION_SINGLE_CANVAS => {
+            let body: SingleCanvasBody = decode_body(data)?;
+            check_dimension(body.width as usize, body.height as usize)?;
+            let canvas = decode_runs(body.width as usize, body.height as usize, body.runs)?;
+            Ok(Board::from_layers(LayerStack::from_canvas(canvas)))
+        }
+        _ => Err(format!("unsupported snapshot version {version}")),
+    }
+}
+
+fn decode_body<T: serde::de::DeserializeOwned>(data: &[u8]) -> Result<T, String> {
+    bincode::serde::decode_from_slice(data, config::standard())
+        .map(|(body, _)| body)
+        .map_err(|e| format!("invalid snapshot: {e}"))
+}
+
+fn encode_runs(canvas: &DrawingPixelCanvas) -> Vec<Run> {
+    let (width, height) = canvas.dimension();
+    let mut runs: Vec<Run> = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            let color = canvas.get_pixel(x, y).to_rgba();
+            match runs.last_mut() {
+                Some(run) if run.color == color => run.count += 1,
+                _ => runs.push(Run { count: 1, color }),
+            }
+        }
+    }
+    runs
+}
+
+fn decode_runs(width: usize, height: usize, runs: Vec<Run>) -> Result<DrawingPixelCanvas, String> {
+    let total: usize = runs.iter().map(|run| run.count as usize).sum();
+    if total != width * height {
+        return Err(format!(
+            "snapshot has {total} pixels for a {width}x{height} canvas"
+        ));
+    }
+
+    let mut canvas = DrawingPixelCanvas::new(width, height);
+    let mut index = 0;
+    for Run { count, color } in runs {
+        let [r, g, b, a] = color;
+        let color = PixelColor::new(r, g, b, a);
+        if color != PixelColor::TRANSPARENT {
+            for i in index..index + count as usize {
+                canvas.draw_pixel_ignore(i % width, i / width, color);
+            }
+        }
+        index += count as usize;
+    }
+    Ok(canvas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::FrameOp;
+    use crate::layers::LayerOp;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 128];
+
+    fn color([r, g, b, a]: [u8; 4]) -> PixelColor {
+        PixelColor::new(r, g, b, a)
+    }
+
+    fn transparent() -> [u8; 4] {
+        PixelColor::TRANSPARENT.to_rgba()
+    }
+
+    /// Snapshot of an old version,written by hand
+    fn with_header(version: u8, body: &impl Serialize) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(version);
+        bincode::serde::encode_into_std_write(body, &mut bytes, config::standard()).unwrap();
+        bytes
+    }
+
+    /// Two frames of two layers,with pixels and layer settings that all have to survive
+    fn sample_board() -> Board {
+        let mut board = Board::new(4, 3);
+        let mut add_layer = LayerOp::Add {
+            id: 0,
+            name: "Ink".to_string(),
+        };
+        board.assign_layer_id(&mut add_layer);
+        board.apply_layer_op(&add_layer).unwrap();
+        board
+            .apply_layer_op(&LayerOp::SetOpacity {
+                id: 1,
+                opacity: 100,
+            })
+            .unwrap();
+        board
+            .apply_layer_op(&LayerOp::SetVisible {
+                id: 0,
+                visible: false,
+            })
+            .unwrap();
+        board
+            .canvas_mut(0, 1)
+            .unwrap()
+            .draw_pixel_ignore(3, 2, color(RED));
+
+        let mut add_frame = FrameOp::Add {
+            id: 0,
+            index: 1,
+            copy_of: Some(0),
+        };
+        board.assign_frame_id(&mut add_frame);
+        board.apply_frame_op(&add_frame).unwrap();
+        board
+            .apply_frame_op(&FrameOp::SetDuration {
+                id: 1,
+                duration_ms: 250,
+            })
+            .unwrap();
+        board
+            .canvas_mut(1, 0)
+            .unwrap()
+            .draw_pixel_ignore(0, 1, color(BLUE));
+        board
+    }
+
+    #[test]
+    fn round_trip_keeps_every_frame_and_layer() {
+        let board = sample_board();
+        let decoded = decode(&encode(&board)).unwrap();
+
+        assert_eq!(decoded.dimension(), (4, 3));
+        assert_eq!(decoded.next_id(), board.next_id());
+        assert_eq!(decoded.frames().len(), 2);
+        for (frame, expected) in decoded.frames().iter().zip(board.frames()) {
+            assert_eq!(frame.id, expected.id);
+            assert_eq!(frame.duration_ms, expected.duration_ms);
+            assert_eq!(frame.layers.next_id(), expected.layers.next_id());
+            assert_eq!(frame.layers.layers().len(), expected.layers.layers().len());
+            for (layer, expected) in frame.layers.layers().iter().zip(expected.layers.layers()) {
+                assert_eq!(layer.info, expected.info);
+                assert_eq!(encode_runs(&layer.canvas), encode_runs(&expected.canvas));
+            }
+        }
+        assert_eq!(
+            decoded
+                .get(0)
+                .unwrap()
+                .layers
+                .get(1)
+                .unwrap()
+                .canvas
+                .get_pixel(3, 2),
+            color(RED)
+        );
+        assert_eq!(
+            decoded
+                .get(1)
+                .unwrap()
+                .layers
+                .get(0)
+                .unwrap()
+                .canvas
+                .get_pixel(0, 1),
+            color(BLUE)
+        );
+    }
+
+    #[test]
+    fn reads_single_frame_snapshots() {
+        let body = SingleFrameBody {
+            width: 2,
+            height: 2,
+            next_id: 5,
+            layers: vec![
+                LayerBody {
+                    info: LayerInfo::new(0, "Background"),
+                    runs: vec![Run {
+                        count: 4,
+                        color: transparent(),
+                    }],
+                },
+                LayerBody {
+                    info: LayerInfo::new(3, "Ink"),
+                    runs: vec![
+                        Run {
+                            count: 3,
+                            color: transparent(),
+                        },
+                        Run {
+                            count: 1,
+                            color: RED,
+                        },
+                    ],
+                },
+            ],
+        };
+        let board = decode(&with_header(VERSION_SINGLE_FRAME, &body)).unwrap();
+
+        assert_eq!(board.dimension(), (2, 2));
+        assert_eq!(board.frames().len(), 1);
+        let layers = &board.first().layers;
+        assert_eq!(layers.next_id(), 5);
+        assert_eq!(layers.layers().len(), 2);
+        assert_eq!(layers.get(3).unwrap().info.name, "Ink");
+        assert_eq!(layers.get(3).unwrap().canvas.get_pixel(1, 1), color(RED));
+        assert_eq!(
+            layers.get(3).unwrap().canvas.get_pixel(0, 0),
+            PixelColor::TRANSPARENT
+        );
+    }
+
+    #[test]
+    fn reads_single_canvas_snapshots() {
+        let body = SingleCanvasBody {
+            width: 3,
+            height: 1,
+            runs: vec![
+                Run {
+                    count: 2,
+                    color: transparent(),
+                },
+                Run {
+                    count: 1,
+                    color: BLUE,
+                },
+            ],
+        };
+        let board = decode(&with_header(VERSION_SINGLE_CANVAS, &body)).unwrap();
+
+        assert_eq!(board.dimension(), (3, 1));
+        assert_eq!(board.frames().len(), 1);
+        let layers = board.first().layers.layers();
+        assert_eq!(layers.len(), 1);
+        assert_eq!(layers[0].canvas.get_pixel(2, 0), color(BLUE));
+        assert_eq!(layers[0].canvas.get_pixel(0, 0), PixelColor::TRANSPARENT);
+    }
+
+    #[test]
+    fn rejects_truncated_snapshots() {
+        let data = encode(&sample_board());
+        for len in 0..data.len() {
+            assert!(
+                decode(&data[..len]).is_err(),
+                "a snapshot cut to {len} bytes was accepted"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_wrong_pixel_counts() {
+        let body = SingleCanvasBody {
+            width: 3,
+            height: 1,
+            runs: vec![Run {
+                count: 2,
+                color: RED,
+            }],
+        };
+        assert!(decode(&with_header(VERSION_SINGLE_CANVAS, &body)).is_err());
+    }
+
+    #[test]
+    fn rejects_boards_of_invalid_size() {
+        for (width, height) in [(0, 1), (1, 0), (501, 1)] {
+            let body = SingleCanvasBody {
+                width,
+                height,
+                runs: vec![Run {
+                    count: width * height,
+                    color: transparent(),
+                }],
+            };
+            assert!(decode(&with_header(VERSION_SINGLE_CANVAS, &body)).is_err());
+        }
+    }
+
+    #[test]
+    fn reads_the_dimension_of_every_version() {
+        assert_eq!(dimension(&encode(&sample_board())), Ok((4, 3)));
+        let body = SingleCanvasBody {
+            width: 3,
+            height: 1,
+            runs: Vec::new(),
+        };
+        assert_eq!(
+            dimension(&with_header(VERSION_SINGLE_CANVAS, &body)),
+            Ok((3, 1))
+        );
+        assert!(dimension(b"PX").is_err());
+    }
+}
@@ -0,0 +1,72 @@
+use std::collections::VecDeque;
+
+use frontend::prelude::PixelColor;
+
+/// Number of colors kept in the recent colors row
+pub const MAX_RECENT_COLORS: usize = 8;
+
+/// Hue in degrees (0..360),saturation and value in 0..1
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hsv {
+    pub h: f64,
+    pub s: f64,
+    pub v: f64,
+}
+
+impl Hsv {
+    pub fn from_rgb([r, g, b]: [u8; 3]) -> Self {
+        let (r, g, b) = (r as f64 / 255.0, g as f64 / 255.0, b as f64 / 255.0);
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let delta = max - min;
+        let h = if delta == 0.0 {
+            0.0
+        } else if max == r {
+            60.0 * ((g - b) / delta).rem_euclid(6.0)
+        } else if max == g {
+            60.0 * ((b - r) / delta + 2.0)
+        } else {
+            60.0 * ((r - g) / delta + 4.0)
+        };
+        let s = if max == 0.0 { 0.0 } else { delta / max };
+        Self { h, s, v: max }
+    }
+
+    pub fn to_rgb(&self) -> [u8; 3] {
+        let c = self.v * self.s;
+        let h = self.h.rem_euclid(360.0) / 60.0;
+        let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+        let (r, g, b) = match h as u32 {
+            0 => (c, x, 0.0),
+            1 => (x, c, 0.0),
+            2 => (0.0, c, x),
+            3 => (0.0, x, c),
+            4 => (x, 0.0, c),
+            _ => (c, 0.0, x),
+        };
+        let m = self.v - c;
+        let channel = |value: f64| ((value + m) * 255.0).round() as u8;
+        [channel(r), channel(g), channel(b)]
+    }
+}
+
+/// Colors used lately,the most recent first
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RecentColors {
+    colors: VecDeque<PixelColor>,
+}
+
+impl RecentColors {
+    /// Move the color to the front,dropping the oldest one when the row is full
+    pub fn push(&mut self, color: PixelColor) {
+        if color == PixelColor::TRANSPARENT || color == PixelColor::ERASE {
+            return;
+        }
+        self.colors.retain(|&recent| recent != color);
+        self.colors.push_front(color);
+        self.colors.truncate(MAX_RECENT_COLORS);
+    }
+    pub fn iter(&self) -> impl Iterator<Item = PixelColor> + '_ {
+        self.colors.iter().copied()
+    }
+}
@@ -0,0 +1,355 @@
+//! Image files made from the frames of the board: png,animated gif and spritesheet with its atlas.
+//! Every export can be upscaled,put on a background,spaced like the screen grid and cropped.
+use std::io::Cursor;
+
+use frontend::layers::blend_onto;
+use frontend::prelude::{DrawingPixelCanvas, PixelColor};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame, ImageFormat, Rgba, RgbaImage, imageops};
+use serde::Serialize;
+
+use crate::types::pixel_canvas::{BORDER_RADIUS, GAP, GridIndex, PIXEL_SIZE};
+use crate::types::selection::GridRect;
+
+/// Largest upscale factor of the exports
+pub const MAX_EXPORT_SCALE: u32 = 32;
+/// Longest side of an exported image,the scale is lowered to stay under it
+pub const MAX_EXPORT_SIDE: u32 = 4096;
+
+/// Rgba bytes of a canvas,row by row,empty cells are fully transparent
+pub fn rgba_bytes(canvas: &DrawingPixelCanvas) -> Vec<u8> {
+    let (width, height) = canvas.dimension();
+    let mut bytes = Vec::with_capacity(width * height * 4);
+    for y in 0..height {
+        for x in 0..width {
+            bytes.extend_from_slice(&cell_rgba(canvas.get_pixel(x, y)));
+        }
+    }
+    bytes
+}
+
+fn cell_rgba(color: PixelColor) -> [u8; 4] {
+    if color == PixelColor::TRANSPARENT || color == PixelColor::ERASE {
+        [0, 0, 0, 0]
+    } else {
+        color.to_rgba()
+    }
+}
+
+/// Part of the grid that is exported
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Crop {
+    /// The whole grid
+    #[default]
+    Full,
+    /// The smallest rectangle holding every drawn cell
+    Content,
+    /// The selected rectangle
+    Selection,
+}
+
+impl Crop {
+    pub const ALL: [Crop; 3] = [Crop::Full, Crop::Content, Crop::Selection];
+    pub fn name(&self) -> &'static str {
+        match self {
+            Crop::Full => "Whole canvas",
+            Crop::Content => "Drawn cells",
+            Crop::Selection => "Selection",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExportOptions {
+    /// Every cell is `scale`x`scale` image pixels
+    pub scale: u32,
+    /// Color behind the cells,None for a transparent background
+    pub background: Option<PixelColor>,
+    /// Space the cells apart with rounded corners,like on the screen
+    pub grid_lines: bool,
+    pub crop: Crop,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            scale: 1,
+            background: None,
+            grid_lines: false,
+            crop: Crop::Full,
+        }
+    }
+}
+
+/// Rectangle of cells exported for the given frames,the content crop holds the drawing of every frame
+pub fn crop_rect<'a>(
+    crop: Crop,
+    frames: impl IntoIterator<Item = &'a DrawingPixelCanvas>,
+    selection: Option<&GridRect>,
+) -> Result<GridRect, String> {
+    let mut frames = frames.into_iter().peekable();
+    let (width, height) = frames.peek().ok_or("there is no frame to export")?.dimension();
+    match crop {
+        Crop::Full => Ok(GridRect {
+            left: 0,
+            top: 0,
+            width,
+            height,
+        }),
+        Crop::Selection => selection.cloned().ok_or("nothing is selected".to_string()),
+        Crop::Content => {
+            let mut bounds: Option<(GridIndex, GridIndex)> = None;
+            for frame in frames {
+                for y in 0..height {
+                    for x in 0..width {
+                        if cell_rgba(frame.get_pixel(x, y))[3] == 0 {
+                            continue;
+                        }
+                        let (min, max) = bounds.get_or_insert((GridIndex { x, y }, GridIndex { x, y }));
+                        min.x = min.x.min(x);
+                        min.y = min.y.min(y);
+                        max.x = max.x.max(x);
+                        max.y = max.y.max(y);
+                    }
+                }
+            }
+            let (min, max) = bounds.ok_or("nothing is drawn")?;
+            Ok(GridRect::from_corners(&min, &max))
+        }
+    }
+}
+
+/// Width of the space between two cells,in image pixels
+fn gap_size(scale: u32) -> u32 {
+    ((scale as f64 * GAP / PIXEL_SIZE).round() as u32).max(1)
+}
+
+/// Check if a pixel of a cell is inside the rounded corners of the cell
+fn inside_rounded_cell(x: u32, y: u32, scale: u32) -> bool {
+    let radius = scale as f64 * BORDER_RADIUS / PIXEL_SIZE;
+    let corner_distance = |offset: u32| {
+        let center = offset as f64 + 0.5;
+        (radius - center).max(center - (scale as f64 - radius)).max(0.0)
+    };
+    let (dx, dy) = (corner_distance(x), corner_distance(y));
+    dx * dx + dy * dy <= radius * radius
+}
+
+/// Length in image pixels of `cells` cells side by side
+fn image_side(cells: usize, scale: u32, grid_lines: bool) -> u32 {
+    let cells = cells as u32;
+    if !grid_lines {
+        return cells.saturating_mul(scale);
+    }
+    let gap = gap_size(scale);
+    cells.saturating_mul(scale + gap).saturating_sub(gap)
+}
+
+/// Scale of the exported cells of `rect`,between 1 and the asked scale.
+/// It is lowered so `columns`x`rows` images of them stay under [`MAX_EXPORT_SIDE`] pixels per side,
+/// an error if they don't even at scale 1.
+pub fn clamp_scale(options: &ExportOptions, rect: &GridRect, columns: usize, rows: usize) -> Result<u32, String> {
+    let fits = |cells: usize, count: usize, scale: u32| {
+        image_side(cells, scale, options.grid_lines).saturating_mul(count as u32) <= MAX_EXPORT_SIDE
+    };
+    (1..=options.scale.clamp(1, MAX_EXPORT_SCALE))
+        .rev()
+        .find(|&scale| fits(rect.width, columns, scale) && fits(rect.height, rows, scale))
+        .ok_or(format!("the export would be larger than {MAX_EXPORT_SIDE} pixels per side"))
+}
+
+/// Image of the cells of `rect`,every cell is `scale`x`scale` image pixels
+fn to_image(canvas: &DrawingPixelCanvas, options: &ExportOptions, rect: &GridRect, scale: u32) -> RgbaImage {
+    let background = options.background.map_or([0, 0, 0, 0], cell_rgba);
+    // Composite the cells over the background once,so translucent pixels are blended with it
+    let (width, height) = canvas.dimension();
+    let mut cells = DrawingPixelCanvas::new(width, height);
+    if let Some(background) = options.background {
+        for y in 0..height {
+            for x in 0..width {
+                cells.draw_pixel_ignore(x, y, background);
+            }
+        }
+    }
+    blend_onto(&mut cells, canvas, u8::MAX);
+    let cell = |x: u32, y: u32| {
+        Rgba(cell_rgba(cells.get_pixel(rect.left + x as usize, rect.top + y as usize)))
+    };
+
+    let image_width = image_side(rect.width, scale, options.grid_lines);
+    let image_height = image_side(rect.height, scale, options.grid_lines);
+    if !options.grid_lines {
+        return RgbaImage::from_fn(image_width, image_height, |x, y| cell(x / scale, y / scale));
+    }
+    let pitch = scale + gap_size(scale);
+    RgbaImage::from_fn(image_width, image_height, |x, y| {
+        let (offset_x, offset_y) = (x % pitch, y % pitch);
+        if offset_x >= scale || offset_y >= scale || !inside_rounded_cell(offset_x, offset_y, scale) {
+            Rgba(background)
+        } else {
+            cell(x / pitch, y / pitch)
+        }
+    })
+}
+
+fn encode_png(image: &RgbaImage) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+        .map_err(|e| format!("Failed to encode png: {e}"))?;
+    Ok(bytes)
+}
+
+pub fn png(canvas: &DrawingPixelCanvas, options: &ExportOptions, rect: &GridRect) -> Result<Vec<u8>, String> {
+    let scale = clamp_scale(options, rect, 1, 1)?;
+    encode_png(&to_image(canvas, options, rect, scale))
+}
+
+/// Looping gif of the frames,each shown for its duration in milliseconds
+pub fn animated_gif(
+    frames: &[(DrawingPixelCanvas, u32)],
+    options: &ExportOptions,
+    rect: &GridRect,
+) -> Result<Vec<u8>, String> {
+    let scale = clamp_scale(options, rect, 1, 1)?;
+    let mut bytes = Vec::new();
+    {
+        let mut encoder = GifEncoder::new(&mut bytes);
+        encoder
+            .set_repeat(Repeat::Infinite)
+            .map_err(|e| format!("Failed to encode gif: {e}"))?;
+        let frames = frames.iter().map(|(canvas, duration_ms)| {
+            Frame::from_parts(
+                to_image(canvas, options, rect, scale),
+                0,
+                0,
+                Delay::from_numer_denom_ms(*duration_ms, 1),
+            )
+        });
+        encoder
+            .encode_frames(frames)
+            .map_err(|e| format!("Failed to encode gif: {e}"))?;
+    }
+    Ok(bytes)
+}
+
+/// How the frames are placed on a spritesheet
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SheetLayout {
+    /// Every frame on one row
+    #[default]
+    Horizontal,
+    /// Rows of frames,as square as possible
+    Grid,
+}
+
+impl SheetLayout {
+    pub const ALL: [SheetLayout; 2] = [SheetLayout::Horizontal, SheetLayout::Grid];
+    pub fn name(&self) -> &'static str {
+        match self {
+            SheetLayout::Horizontal => "Row",
+            SheetLayout::Grid => "Grid",
+        }
+    }
+    /// Number of frames on a row of the sheet
+    fn columns(&self, frame_count: usize) -> usize {
+        match self {
+            SheetLayout::Horizontal => frame_count.max(1),
+            SheetLayout::Grid => (frame_count as f64).sqrt().ceil().max(1.0) as usize,
+        }
+    }
+}
+
+/// Frame rectangles of a spritesheet,in the json array format of TexturePacker and Aseprite
+#[derive(Serialize, Debug)]
+pub struct Atlas {
+    pub frames: Vec<AtlasFrame>,
+    pub meta: AtlasMeta,
+}
+
+#[derive(Serialize, Debug)]
+pub struct AtlasFrame {
+    pub filename: String,
+    pub frame: AtlasRect,
+    /// Milliseconds
+    pub duration: u32,
+}
+
+#[derive(Serialize, Debug)]
+pub struct AtlasRect {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+#[derive(Serialize, Debug)]
+pub struct AtlasMeta {
+    /// File name of the sheet image
+    pub image: String,
+    pub size: AtlasSize,
+    pub scale: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct AtlasSize {
+    pub w: u32,
+    pub h: u32,
+}
+
+impl Atlas {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("an atlas is always serializable")
+    }
+}
+
+/// Every frame on a single png,with the atlas of their rectangles.
+/// `image_name` is the file name the png is saved as,the atlas refers to it.
+pub fn spritesheet(
+    frames: &[(DrawingPixelCanvas, u32)],
+    layout: SheetLayout,
+    options: &ExportOptions,
+    rect: &GridRect,
+    image_name: &str,
+) -> Result<(Vec<u8>, Atlas), String> {
+    let columns = layout.columns(frames.len());
+    let rows = frames.len().div_ceil(columns);
+    let scale = clamp_scale(options, rect, columns, rows)?;
+    let images: Vec<RgbaImage> = frames
+        .iter()
+        .map(|(canvas, _)| to_image(canvas, options, rect, scale))
+        .collect();
+    let first = images.first().ok_or("there is no frame to export")?;
+    let (frame_width, frame_height) = first.dimensions();
+
+    let mut sheet = RgbaImage::new(frame_width * columns as u32, frame_height * rows as u32);
+    let mut atlas_frames = Vec::with_capacity(frames.len());
+    for (index, (image, (_, duration_ms))) in images.iter().zip(frames).enumerate() {
+        let x = (index % columns) as u32 * frame_width;
+        let y = (index / columns) as u32 * frame_height;
+        imageops::replace(&mut sheet, image, x as i64, y as i64);
+        atlas_frames.push(AtlasFrame {
+            filename: format!("frame_{index}"),
+            frame: AtlasRect {
+                x,
+                y,
+                w: frame_width,
+                h: frame_height,
+            },
+            duration: *duration_ms,
+        });
+    }
+
+    let atlas = Atlas {
+        frames: atlas_frames,
+        meta: AtlasMeta {
+            image: image_name.to_string(),
+            size: AtlasSize {
+                w: sheet.width(),
+                h: sheet.height(),
+            },
+            scale: scale.to_string(),
+        },
+    };
+    Ok((encode_png(&sheet)?, atlas))
+}
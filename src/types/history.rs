@@ -0,0 +1,146 @@
+use frontend::prelude::{DrawingPixelCanvas, PixelColor};
+
+use crate::types::pixel_canvas::GridIndex;
+
+/// Maximum number of strokes kept for undo
+pub const MAX_HISTORY: usize = 100;
+
+/// A pixel changed by a stroke,with its color on the layer before and after the stroke
+#[derive(Clone, Debug)]
+pub struct PixelChange {
+    pub index: GridIndex,
+    pub before: PixelColor,
+    pub after: PixelColor,
+}
+
+/// One drawing operation (pen line,bucket fill,eraser...) from mouse down to mouse up
+#[derive(Clone, Debug, Default)]
+pub struct Stroke {
+    /// Frame and layer the stroke was drawn on
+    pub frame: u32,
+    pub layer: u32,
+    pub changes: Vec<PixelChange>,
+}
+
+impl Stroke {
+    /// The stroke that takes this one back
+    pub fn inverse(&self) -> Stroke {
+        Stroke {
+            frame: self.frame,
+            layer: self.layer,
+            changes: self
+                .changes
+                .iter()
+                .map(|change| PixelChange {
+                    index: change.index.clone(),
+                    before: change.after,
+                    after: change.before,
+                })
+                .collect(),
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+/// Stroke being drawn,used to find which pixel the stroke changed once it ends
+#[derive(Clone, Debug)]
+pub struct StrokeRecorder {
+    frame: u32,
+    layer: u32,
+    /// Pixels of the layer when the stroke started
+    before: DrawingPixelCanvas,
+    /// Every pixel the stroke painted on,the rest is left transparent
+    touched: DrawingPixelCanvas,
+}
+
+impl StrokeRecorder {
+    pub fn new(frame: u32, layer: u32, pixels: DrawingPixelCanvas) -> Self {
+        let (width, height) = pixels.dimension();
+        Self {
+            frame,
+            layer,
+            before: pixels,
+            touched: DrawingPixelCanvas::new(width, height),
+        }
+    }
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+    pub fn layer(&self) -> u32 {
+        self.layer
+    }
+    /// Canvas the drawing operation has to be repeated on
+    pub fn touched_mut(&mut self) -> &mut DrawingPixelCanvas {
+        &mut self.touched
+    }
+    /// Compare the touched pixels with the pixels of the layer after the stroke
+    pub fn finish(self, pixels: &DrawingPixelCanvas) -> Stroke {
+        let (width, height) = self.touched.dimension();
+        let mut changes = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                if self.touched.get_pixel(x, y) == PixelColor::TRANSPARENT {
+                    continue;
+                }
+                let before = self.before.get_pixel(x, y);
+                let after = pixels.get_pixel(x, y);
+                if before != after {
+                    changes.push(PixelChange {
+                        index: GridIndex { x, y },
+                        before,
+                        after,
+                    });
+                }
+            }
+        }
+        Stroke {
+            frame: self.frame,
+            layer: self.layer,
+            changes,
+        }
+    }
+}
+
+/// Undo and redo stacks of the local user's strokes
+#[derive(Clone, Debug, Default)]
+pub struct History {
+    undo_stack: Vec<Stroke>,
+    redo_stack: Vec<Stroke>,
+}
+
+impl History {
+    /// Record a new stroke,this invalidates everything that could be redone
+    pub fn push(&mut self, stroke: Stroke) {
+        if stroke.is_empty() {
+            return;
+        }
+        self.redo_stack.clear();
+        self.push_undo(stroke);
+    }
+    fn push_undo(&mut self, stroke: Stroke) {
+        self.undo_stack.push(stroke);
+        if self.undo_stack.len() > MAX_HISTORY {
+            self.undo_stack.remove(0);
+        }
+    }
+    /// Take the last stroke out,it is kept to be redone
+    pub fn undo(&mut self) -> Option<Stroke> {
+        let stroke = self.undo_stack.pop()?;
+        self.redo_stack.push(stroke.clone());
+        Some(stroke.inverse())
+    }
+    /// Take the last undone stroke out,it is kept to be undone again
+    pub fn redo(&mut self) -> Option<Stroke> {
+        let stroke = self.redo_stack.pop()?;
+        self.push_undo(stroke.clone());
+        Some(stroke)
+    }
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+}
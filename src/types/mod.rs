@@ -1 +1,10 @@
-pub mod pixel_canvas;
\ No newline at end of file
+pub mod color;
+pub mod export;
+pub mod history;
+pub mod pixel_canvas;
+pub mod presence;
+pub mod quantize;
+pub mod recent;
+pub mod selection;
+pub mod shapes;
+pub mod sync;
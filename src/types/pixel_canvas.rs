@@ -1,14 +1,27 @@
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, VecDeque};
 
 use frontend::prelude::{BitMatrix, PixelColor, Vec2};
+use frontend::{
+    board::{Board, Frame, FrameOp},
+    layers::{LayerOp, LayerStack, blend_onto},
+    protocol::CanvasDelta,
+    snapshot,
+};
 use leptos::logging::log;
-use web_sys::CanvasRenderingContext2d;
+use web_sys::{CanvasRenderingContext2d, js_sys};
 
 use crate::{
     components::canvas::{GREEN_TOUCHED, PEN_TOUCHED},
     prelude::{
         DrawingPixelCanvas, Position, RectSize, Rectangle, get_window_rect, get_window_size,
     },
+    types::{
+        export::{self, Crop, ExportOptions},
+        history::{History, Stroke, StrokeRecorder},
+        presence::RemoteCursor,
+        selection::{GridRect, PixelRegion, Selection},
+        shapes::Shape,
+    },
 };
 
 // Constants for pixel canvas styling
@@ -21,7 +34,18 @@ pub const PIXEL_HOVER_COLOR: &str = "#bbbbbb";
 pub const PIXEL_STROKE_COLOR: &str = "#111111";
 pub const PIXEL_LINE_WIDTH: f64 = 1.0;
 pub const CANVAS_BACKGROUND_COLOR: &str = "#f0f0f0";
-#[derive(Clone, Debug)]
+pub const SELECTION_LINE_WIDTH: f64 = 2.0;
+/// Length of a dash of the selection marching ants
+pub const SELECTION_DASH: f64 = 6.0;
+/// Opacity of the previous frame shown under the active one
+pub const ONION_PREVIOUS_ALPHA: f64 = 0.4;
+/// Opacity of the next frame shown under the active one
+pub const ONION_NEXT_ALPHA: f64 = 0.2;
+/// Width of the outline drawn around the cell hovered by another user
+pub const CURSOR_LINE_WIDTH: f64 = 3.0;
+/// Font of the name tag of the other users' cursors
+pub const CURSOR_TAG_FONT: &str = "12px sans-serif";
+#[derive(Clone, Debug, PartialEq)]
 pub struct GridIndex {
     pub x: usize,
     pub y: usize,
@@ -35,10 +59,25 @@ pub struct PixelCanvas {
     //limited by bound checking
     /// Zoom level (1.0 = normal, 2.0 = 2x zoom, etc.)
     zoom: f64,
-    /// Drawing canvas for pixel data
-    main_canvas: DrawingPixelCanvas,
-    drawing_canvas: DrawingPixelCanvas,
+    /// Frames and layers as merged by the server
+    board: Board,
+    /// Frame the user draws on
+    active_frame: u32,
+    /// Layer the user draws on
+    active_layer: u32,
+    /// Pixels drawn but not sent yet,per (frame id,layer id)
+    drawing: BTreeMap<(u32, u32), DrawingPixelCanvas>,
+    /// Show the previous and next frames under the active one
+    onion_skin: bool,
+    /// Previews (shapes,moved selection) shown on the active layer
     temp_canvas: DrawingPixelCanvas,
+    /// Undo/redo stacks of the strokes drawn by this user
+    history: History,
+    /// Stroke currently being drawn,None when the mouse is up
+    stroke: Option<StrokeRecorder>,
+    selection: Option<Selection>,
+    /// Last copied region,used when the system clipboard has no image
+    clipboard: Option<PixelRegion>,
 }
 
 impl Default for PixelCanvas {
@@ -46,9 +85,16 @@ impl Default for PixelCanvas {
         Self {
             position: Position::new(-20.0, -20.0),
             zoom: 2.0,
-            main_canvas: DrawingPixelCanvas::new(DEFAULT_GRID_SIZE, DEFAULT_GRID_SIZE),
-            drawing_canvas: DrawingPixelCanvas::new(DEFAULT_GRID_SIZE, DEFAULT_GRID_SIZE),
+            board: Board::new(DEFAULT_GRID_SIZE, DEFAULT_GRID_SIZE),
+            active_frame: 0,
+            active_layer: 0,
+            drawing: BTreeMap::new(),
+            onion_skin: false,
             temp_canvas: DrawingPixelCanvas::new(DEFAULT_GRID_SIZE, DEFAULT_GRID_SIZE),
+            history: History::default(),
+            stroke: None,
+            selection: None,
+            clipboard: None,
         }
     }
 }
@@ -59,9 +105,16 @@ impl PixelCanvas {
         Self {
             position: Position::new(x, y),
             zoom,
-            main_canvas: DrawingPixelCanvas::new(size.x, size.y),
-            drawing_canvas: DrawingPixelCanvas::new(size.x, size.y),
+            board: Board::new(size.x, size.y),
+            active_frame: 0,
+            active_layer: 0,
+            drawing: BTreeMap::new(),
+            onion_skin: false,
             temp_canvas: DrawingPixelCanvas::new(size.x, size.y),
+            history: History::default(),
+            stroke: None,
+            selection: None,
+            clipboard: None,
         }
     }
     pub fn new_in_middle(size:GridIndex)->Self{
@@ -75,50 +128,553 @@ impl PixelCanvas {
         self.position=Position::from((window_size-size)*0.5);
         self
     }
-    /// Get mutable reference to drawing canvas
+    /// Replace the frames,from a snapshot or from the raw bytes of the bottom layer of the first frame
     pub fn assign_pixel_bytes<'a>(&mut self, data: &'a [u8]) -> Result<&'a [u8], String> {
-        self.main_canvas.assign_bytes(data)
+        if !snapshot::is_snapshot(data) {
+            return self.board.first_mut().layers.bottom_mut().canvas.assign_bytes(data);
+        }
+        let board = snapshot::decode(data)?;
+        if board.dimension() != self.grid_dimension() {
+            // The session was created with another size than the one asked in the url,or was resized
+            let (width, height) = board.dimension();
+            self.drawing.clear();
+            self.temp_canvas = DrawingPixelCanvas::new(width, height);
+            self.history = History::default();
+            self.stroke = None;
+            self.selection = None;
+        }
+        self.board = board;
+        self.forget_removed_layers();
+        Ok(&data[data.len()..])
     }
-    pub fn image_export(&self) -> Result<Vec<u8>, String> {
-        self.rendered_canvas().to_png_bytes()
+    /// Flattened image of the visible layers of the active frame
+    pub fn image_export(&self, options: &ExportOptions) -> Result<Vec<u8>, String> {
+        let image = self.rendered_canvas();
+        let rect = self.export_rect(options.crop, [&image])?;
+        export::png(&image, options, &rect)
     }
-    pub fn update_drawing(&mut self) {
-        self.main_canvas.merge_top(&self.drawing_canvas);
-        self.drawing_canvas.clear();
+    /// Cells exported with the given crop,the content crop holds the drawing of every given frame
+    pub fn export_rect<'a>(
+        &self,
+        crop: Crop,
+        frames: impl IntoIterator<Item = &'a DrawingPixelCanvas>,
+    ) -> Result<GridRect, String> {
+        let selection = self
+            .selection
+            .as_ref()
+            .map(|selection| selection.rect.clamp(self.grid_dimension()));
+        export::crop_rect(crop, frames, selection.as_ref())
     }
-    pub fn main_canvas_to_bytes(&self) -> Vec<u8> {
-        self.main_canvas.to_bytes()
+    /// Image of a single layer of the active frame,with its unsent drawing
+    pub fn layer_export(&self, id: u32) -> Result<Vec<u8>, String> {
+        self.layer_pixels(self.active_frame, id)
+            .ok_or(format!("no layer {id}"))?
+            .to_png_bytes()
     }
-    pub fn drawing_canvas_to_bytes(&self) -> Vec<u8> {
-        self.drawing_canvas.to_bytes()
+    /// The unsent drawing was sent,merge it in the layers
+    pub fn update_drawing(&mut self) {
+        for ((frame, layer), drawing) in std::mem::take(&mut self.drawing) {
+            if let Some(canvas) = self.board.canvas_mut(frame, layer) {
+                canvas.merge_top(&drawing);
+            }
+        }
+    }
+    /// Pixels drawn since the last update_drawing,one delta per layer of a frame drawn on.
+    /// The deltas are numbered from `first_seq`.
+    pub fn drawing_deltas(&self, first_seq: u64) -> Vec<CanvasDelta> {
+        self.drawing
+            .iter()
+            .filter_map(|(&(frame, layer), drawing)| {
+                CanvasDelta::from_drawing(0, frame, layer, drawing)
+            })
+            .zip(first_seq..)
+            .map(|(delta, seq)| CanvasDelta { seq, ..delta })
+            .collect()
     }
-    pub fn is_drawing_transperent(&self) -> bool {
-        self.rendered_canvas().is_transpernet_debug()
+    /// Merge a delta received from the server in its layer,unless it was drawn on a board of another size
+    pub fn apply_delta(&mut self, delta: &CanvasDelta) {
+        if !delta.fits(self.grid_dimension()) {
+            return;
+        }
+        if let Some(canvas) = self.board.canvas_mut(delta.frame, delta.layer) {
+            delta.apply(canvas);
+        }
     }
     pub fn grid_dimension(&self) -> (usize, usize) {
-        self.main_canvas.dimension()
+        self.board.dimension()
     }
+    /// Every visible layer of the active frame with its unsent drawing,and the preview on the active layer
     pub fn rendered_canvas(&self) -> DrawingPixelCanvas {
-        let mut rendered_canvas = self.main_canvas.clone();
-        rendered_canvas.merge_top(&self.drawing_canvas);
-        rendered_canvas
+        self.rendered_frame(self.active_frame)
+            .expect("the active frame always exists")
+    }
+    /// Every visible layer of a frame with its unsent drawing,None if there is no such frame
+    pub fn rendered_frame(&self, id: u32) -> Option<DrawingPixelCanvas> {
+        let frame = self.board.get(id)?;
+        let (width, height) = self.grid_dimension();
+        let mut rendered_canvas = DrawingPixelCanvas::new(width, height);
+        for layer in frame.layers.layers().iter().filter(|layer| layer.info.visible) {
+            let mut pixels = layer.canvas.clone();
+            if let Some(drawing) = self.drawing.get(&(id, layer.info.id)) {
+                pixels.merge_top(drawing);
+            }
+            if id == self.active_frame && layer.info.id == self.active_layer {
+                pixels.merge_top(&self.temp_canvas);
+            }
+            blend_onto(&mut rendered_canvas, &pixels, layer.info.opacity);
+        }
+        Some(rendered_canvas)
+    }
+    /// Pixels of a layer of a frame with its unsent drawing,None if there is no such layer
+    fn layer_pixels(&self, frame: u32, layer: u32) -> Option<DrawingPixelCanvas> {
+        let mut pixels = self.board.get(frame)?.layers.get(layer)?.canvas.clone();
+        if let Some(drawing) = self.drawing.get(&(frame, layer)) {
+            pixels.merge_top(drawing);
+        }
+        Some(pixels)
+    }
+    /// Color shown at a grid cell,None outside the grid or on an empty cell
+    pub fn sample_color(&self, pos: &GridIndex) -> Option<PixelColor> {
+        let (width, height) = self.grid_dimension();
+        if pos.x >= width || pos.y >= height {
+            return None;
+        }
+        let color = self.rendered_canvas().get_pixel(pos.x, pos.y);
+        (color != PixelColor::TRANSPARENT && color != PixelColor::ERASE).then_some(color)
+    }
+    /// Apply a drawing operation on the active layer of the active frame,and on the current stroke if there is one.
+    /// Hidden and locked layers are left untouched.
+    fn paint(&mut self, op: impl Fn(&mut DrawingPixelCanvas)) {
+        if !self.can_draw() {
+            return;
+        }
+        op(self.drawing_mut(self.active_frame, self.active_layer));
+        if let Some(stroke) = &mut self.stroke {
+            op(stroke.touched_mut());
+        }
+    }
+    /// Unsent drawing of a layer of a frame
+    fn drawing_mut(&mut self, frame: u32, layer: u32) -> &mut DrawingPixelCanvas {
+        let (width, height) = self.grid_dimension();
+        self.drawing
+            .entry((frame, layer))
+            .or_insert_with(|| DrawingPixelCanvas::new(width, height))
     }
     /// Implement lineDraw for PixelCanvas as requested
     pub fn line_draw(&mut self, pos1: GridIndex, pos2: GridIndex, color: PixelColor) {
-        self.drawing_canvas
-            .draw_line(pos1.x, pos1.y, pos2.x, pos2.y, color);
+        self.paint(|canvas| canvas.draw_line(pos1.x, pos1.y, pos2.x, pos2.y, color));
     }
     
     pub fn pixel_draw(&mut self, pos: GridIndex, color: PixelColor) {
-        self.drawing_canvas.draw_pixel_ignore(pos.x, pos.y, color);
+        self.paint(|canvas| canvas.draw_pixel_ignore(pos.x, pos.y, color));
         log!(
             "pixel color after drawing: {:?} in index {pos:?}",
-            self.drawing_canvas.get_pixel(pos.x, pos.y)
+            self.drawing
+                .get(&(self.active_frame, self.active_layer))
+                .map(|drawing| drawing.get_pixel(pos.x, pos.y))
         );
     }
     pub fn bucket_draw(&mut self,pos:GridIndex,color:PixelColor){
-        self.drawing_canvas.bucket_fill(pos.x,pos.y,color,&self.rendered_canvas());
+        // Fill the area of the active layer,not of the composited image
+        let Some(reference) = self.layer_pixels(self.active_frame, self.active_layer) else {
+            return;
+        };
+        self.paint(|canvas| canvas.bucket_fill(pos.x, pos.y, color, &reference));
+    }
+    /// Draw an imported image over the active layer,as one undoable stroke.
+    /// Transparent pixels of the image leave the layer as it is.
+    pub fn import_region(&mut self, region: &PixelRegion) {
+        self.clear_selection();
+        let origin = GridIndex { x: 0, y: 0 };
+        self.with_stroke(|pc| pc.paint(|canvas| region.paste_on(canvas, &origin)));
+    }
+    /// Rubber-band preview of a shape on the temp canvas,replacing the previous preview
+    pub fn preview_shape(&mut self, shape: Shape, start: &GridIndex, end: &GridIndex, color: PixelColor) {
+        self.temp_canvas.clear();
+        for pos in shape.pixels(start, end) {
+            self.temp_canvas.draw_pixel_ignore(pos.x, pos.y, color);
+        }
+    }
+    /// Move the preview from the temp canvas to the drawing canvas
+    pub fn commit_preview(&mut self) {
+        let preview = std::mem::replace(
+            &mut self.temp_canvas,
+            DrawingPixelCanvas::new(self.grid_dimension().0, self.grid_dimension().1),
+        );
+        self.paint(|canvas| canvas.merge_top(&preview));
+    }
+    //region selection
+    pub fn selection(&self) -> Option<&Selection> {
+        self.selection.as_ref()
+    }
+    pub fn selection_contains(&self, pos: &GridIndex) -> bool {
+        self.selection
+            .as_ref()
+            .is_some_and(|selection| selection.rect.contains(pos))
+    }
+    /// Select the rectangle between two corners,dropping the previous selection
+    pub fn select(&mut self, a: &GridIndex, b: &GridIndex) {
+        self.commit_selection();
+        let rect = GridRect::from_corners(a, b).clamp(self.grid_dimension());
+        self.selection = Some(Selection {
+            rect,
+            floating: None,
+            source: None,
+        });
+    }
+    pub fn select_all(&mut self) {
+        let (width, height) = self.grid_dimension();
+        self.select(
+            &GridIndex { x: 0, y: 0 },
+            &GridIndex {
+                x: width.saturating_sub(1),
+                y: height.saturating_sub(1),
+            },
+        );
+    }
+    /// Drop the floating pixels where they are,and unselect
+    pub fn clear_selection(&mut self) {
+        self.commit_selection();
+        self.selection = None;
+    }
+    /// Forget the selection,a move or paste that wasn't dropped yet is discarded
+    pub fn cancel_selection(&mut self) {
+        if self
+            .selection
+            .take()
+            .is_some_and(|selection| selection.floating.is_some())
+        {
+            self.temp_canvas.clear();
+        }
+    }
+    /// Lift the selected pixels so they can be moved.
+    /// The source is erased when the pixels are dropped,unless `copy`.
+    pub fn lift_selection(&mut self, copy: bool) {
+        let Some(selection) = &self.selection else {
+            return;
+        };
+        if selection.floating.is_some() {
+            return;
+        }
+        let rect = selection.rect.clone();
+        let Some(pixels) = self.layer_pixels(self.active_frame, self.active_layer) else {
+            return;
+        };
+        let region = PixelRegion::copy_from(&pixels, &rect);
+        self.selection = Some(Selection {
+            source: (!copy).then(|| rect.clone()),
+            rect,
+            floating: Some(region),
+        });
+        self.preview_floating();
+    }
+    pub fn move_selection(&mut self, dx: isize, dy: isize) {
+        if let Some(selection) = &mut self.selection {
+            selection.rect.shift(dx, dy);
+        }
+        self.preview_floating();
+    }
+    /// Draw the floating pixels at their current place,the selection stays.
+    /// Erasing the source and drawing happen together,so they are synced as one delta.
+    pub fn commit_selection(&mut self) {
+        let grid_dimension = self.grid_dimension();
+        let Some(selection) = &mut self.selection else {
+            return;
+        };
+        let Some(region) = selection.floating.take() else {
+            return;
+        };
+        let source = selection.source.take();
+        let at = GridIndex {
+            x: selection.rect.left,
+            y: selection.rect.top,
+        };
+        // Moved or pasted past the right or bottom edge,only the cells of the grid stay selected
+        selection.rect = selection.rect.clamp(grid_dimension);
+        self.temp_canvas.clear();
+        self.with_stroke(|pc| {
+            pc.paint(|canvas| {
+                if let Some(source) = &source {
+                    erase_rect(canvas, source);
+                }
+                region.paste_on(canvas, &at);
+            })
+        });
+    }
+    /// Copy the selected pixels,None if nothing is selected
+    pub fn copy_selection(&mut self) -> Option<PixelRegion> {
+        let selection = self.selection.as_ref()?;
+        let region = match &selection.floating {
+            Some(region) => region.clone(),
+            None => PixelRegion::copy_from(
+                &self.layer_pixels(self.active_frame, self.active_layer)?,
+                &selection.rect,
+            ),
+        };
+        self.clipboard = Some(region.clone());
+        Some(region)
+    }
+    pub fn cut_selection(&mut self) -> Option<PixelRegion> {
+        let region = self.copy_selection()?;
+        self.delete_selection();
+        Some(region)
+    }
+    /// Erase the selected pixels and unselect
+    pub fn delete_selection(&mut self) {
+        let Some(selection) = self.selection.take() else {
+            return;
+        };
+        self.temp_canvas.clear();
+        let erased = match (&selection.floating, &selection.source) {
+            (None, _) => Some(&selection.rect),
+            // Lifted pixels: only their source is on the canvas
+            (Some(_), source) => source.as_ref(),
+        };
+        if let Some(rect) = erased {
+            self.with_stroke(|pc| pc.paint(|canvas| erase_rect(canvas, rect)));
+        }
+    }
+    /// Show a region floating at `at`,it is drawn on commit_selection
+    pub fn paste(&mut self, region: PixelRegion, at: &GridIndex) {
+        self.clear_selection();
+        let rect = GridRect {
+            left: at.x,
+            top: at.y,
+            width: region.width,
+            height: region.height,
+        };
+        self.selection = Some(Selection {
+            rect,
+            floating: Some(region),
+            source: None,
+        });
+        self.preview_floating();
+    }
+    /// Paste the last copied region,returns false if nothing was copied
+    pub fn paste_clipboard(&mut self, at: &GridIndex) -> bool {
+        let Some(region) = self.clipboard.clone() else {
+            return false;
+        };
+        self.paste(region, at);
+        true
+    }
+    /// Show the floating pixels,and the hole they leave,on the temp canvas
+    fn preview_floating(&mut self) {
+        self.temp_canvas.clear();
+        let Some(Selection {
+            rect,
+            floating: Some(region),
+            source,
+        }) = &self.selection
+        else {
+            return;
+        };
+        if let Some(source) = source {
+            erase_rect(&mut self.temp_canvas, source);
+        }
+        let at = GridIndex {
+            x: rect.left,
+            y: rect.top,
+        };
+        region.paste_on(&mut self.temp_canvas, &at);
+    }
+    //endregion
+    //region history
+    /// Run an operation as its own undoable stroke,unless a stroke is already being drawn
+    fn with_stroke(&mut self, op: impl FnOnce(&mut Self)) {
+        let own_stroke = self.stroke.is_none();
+        if own_stroke {
+            self.begin_stroke();
+        }
+        op(self);
+        if own_stroke {
+            self.end_stroke();
+        }
+    }
+    /// Start recording a stroke on the active layer,every drawing until end_stroke can be undone as one
+    pub fn begin_stroke(&mut self) {
+        self.stroke = self
+            .layer_pixels(self.active_frame, self.active_layer)
+            .map(|pixels| StrokeRecorder::new(self.active_frame, self.active_layer, pixels));
     }
+    pub fn end_stroke(&mut self) {
+        let Some(stroke) = self.stroke.take() else {
+            return;
+        };
+        // The frame or layer may have been removed by a collaborator meanwhile
+        if let Some(pixels) = self.layer_pixels(stroke.frame(), stroke.layer()) {
+            let stroke = stroke.finish(&pixels);
+            self.history.push(stroke);
+        }
+    }
+    pub fn can_undo(&self) -> bool {
+        self.stroke.is_none() && self.history.can_undo()
+    }
+    pub fn can_redo(&self) -> bool {
+        self.stroke.is_none() && self.history.can_redo()
+    }
+    /// Take back the last stroke.
+    /// The inverse is drawn on the drawing canvas,so it is synced like any other drawing
+    pub fn undo(&mut self) {
+        if self.stroke.is_some() {
+            return;
+        }
+        if let Some(stroke) = self.history.undo() {
+            self.apply_stroke(&stroke);
+        }
+    }
+    pub fn redo(&mut self) {
+        if self.stroke.is_some() {
+            return;
+        }
+        if let Some(stroke) = self.history.redo() {
+            self.apply_stroke(&stroke);
+        }
+    }
+    fn apply_stroke(&mut self, stroke: &Stroke) {
+        let editable = self
+            .layers()
+            .get(stroke.layer)
+            .is_some_and(|layer| layer.info.is_editable());
+        let Some(pixels) = self
+            .layer_pixels(stroke.frame, stroke.layer)
+            .filter(|_| editable)
+        else {
+            return;
+        };
+        let drawing = self.drawing_mut(stroke.frame, stroke.layer);
+        for change in &stroke.changes {
+            let GridIndex { x, y } = change.index;
+            // Leave the pixels a collaborator changed after this stroke
+            if pixels.get_pixel(x, y) != change.before {
+                continue;
+            }
+            let color = if change.after == PixelColor::TRANSPARENT {
+                PixelColor::ERASE
+            } else {
+                change.after
+            };
+            drawing.draw_pixel_ignore(x, y, color);
+        }
+    }
+    //endregion
+    //region layers
+    /// Layers of the active frame,every frame has the same layers
+    pub fn layers(&self) -> &LayerStack {
+        &self.frame().layers
+    }
+    pub fn active_layer(&self) -> u32 {
+        self.active_layer
+    }
+    /// The active layer exists,is visible and isn't locked
+    pub fn can_draw(&self) -> bool {
+        self.layers()
+            .get(self.active_layer)
+            .is_some_and(|layer| layer.info.is_editable())
+    }
+    pub fn set_active_layer(&mut self, id: u32) {
+        if self.layers().get(id).is_none() || id == self.active_layer {
+            return;
+        }
+        // Previews and floating pixels belong to the layer they were made on
+        self.clear_selection();
+        self.commit_preview();
+        self.active_layer = id;
+    }
+    /// Apply a layer change,from this user or from the server
+    pub fn apply_layer_op(&mut self, op: &LayerOp) -> Result<(), String> {
+        if let LayerOp::Remove { id } = op {
+            if *id == self.active_layer {
+                self.cancel_selection();
+                self.temp_canvas.clear();
+                self.stroke = None;
+            }
+        }
+        self.board.apply_layer_op(op)?;
+        self.forget_removed_layers();
+        Ok(())
+    }
+    /// Drop the unsent drawing of frames and layers that don't exist anymore,
+    /// and pick another active frame or layer if it was removed
+    fn forget_removed_layers(&mut self) {
+        let board = &self.board;
+        self.drawing.retain(|&(frame, layer), _| {
+            board.get(frame).is_some_and(|frame| frame.layers.get(layer).is_some())
+        });
+        if self.board.get(self.active_frame).is_none() {
+            self.active_frame = self.board.first().id;
+        }
+        if self.layers().get(self.active_layer).is_none() {
+            self.active_layer = self.layers().top().info.id;
+        }
+    }
+    //endregion
+    //region frames
+    pub fn frames(&self) -> &[Frame] {
+        self.board.frames()
+    }
+    pub fn active_frame(&self) -> u32 {
+        self.active_frame
+    }
+    fn frame(&self) -> &Frame {
+        self.board
+            .get(self.active_frame)
+            .expect("the active frame always exists")
+    }
+    pub fn set_active_frame(&mut self, id: u32) {
+        if self.board.get(id).is_none() || id == self.active_frame {
+            return;
+        }
+        // Previews and floating pixels belong to the frame they were made on
+        self.clear_selection();
+        self.commit_preview();
+        self.active_frame = id;
+    }
+    /// Every frame flattened,in playback order,with its duration
+    pub fn frame_images(&self) -> Vec<(DrawingPixelCanvas, u32)> {
+        self.board
+            .frames()
+            .iter()
+            .filter_map(|frame| Some((self.rendered_frame(frame.id)?, frame.duration_ms)))
+            .collect()
+    }
+    /// Apply a frame change,from this user or from the server
+    pub fn apply_frame_op(&mut self, op: &FrameOp) -> Result<(), String> {
+        if let FrameOp::Remove { id } = op {
+            if *id == self.active_frame {
+                self.cancel_selection();
+                self.temp_canvas.clear();
+                self.stroke = None;
+            }
+        }
+        self.board.apply_frame_op(op)?;
+        self.forget_removed_layers();
+        Ok(())
+    }
+    pub fn onion_skin(&self) -> bool {
+        self.onion_skin
+    }
+    pub fn set_onion_skin(&mut self, onion_skin: bool) {
+        self.onion_skin = onion_skin;
+    }
+    /// Frames shown under the active one with their opacity,the previous first
+    fn onion_frames(&self) -> Vec<(DrawingPixelCanvas, f64)> {
+        if !self.onion_skin {
+            return Vec::new();
+        }
+        let frames = self.board.frames();
+        let Some(index) = self.board.index_of(self.active_frame) else {
+            return Vec::new();
+        };
+        let previous = index.checked_sub(1).map(|i| (i, ONION_PREVIOUS_ALPHA));
+        let next = (index + 1 < frames.len()).then_some((index + 1, ONION_NEXT_ALPHA));
+        previous
+            .into_iter()
+            .chain(next)
+            .filter_map(|(i, alpha)| Some((self.rendered_frame(frames[i].id)?, alpha)))
+            .collect()
+    }
+    //endregion
     pub fn set_position(&mut self, x: f64, y: f64) {
         self.position = Position::new(x, y);
         self.clamp_position();
@@ -145,12 +701,19 @@ impl PixelCanvas {
         Rectangle::from_pos_size(self.position, self.get_size())
     }
     /// Draw the pixel canvas grid
-    pub fn draw(&self, context: &CanvasRenderingContext2d, mouse_pos: Option<Position>) {
+    /// `dash_offset` animates the marching ants of the selection
+    pub fn draw(
+        &self,
+        context: &CanvasRenderingContext2d,
+        mouse_pos: Option<Position>,
+        dash_offset: f64,
+    ) {
         // Apply zoom transformations
         let scaled_pixel_size = PIXEL_SIZE * self.zoom;
         let scaled_gap = GAP * self.zoom;
         let scaled_border_radius = BORDER_RADIUS * self.zoom;
         let rendered_canvas = self.rendered_canvas(); //self.rendered_canvas();
+        let onion_frames = self.onion_frames();
         // Set up stroke properties once
         context.set_stroke_style_str(PIXEL_STROKE_COLOR);
         context.set_line_width(PIXEL_LINE_WIDTH);
@@ -190,9 +753,96 @@ impl PixelCanvas {
                     scaled_border_radius,
                 );
 
+                // Onion skin: pixels of the neighbouring frames,faded,on the empty cells
+                if !is_hovered && pixel_color == PixelColor::TRANSPARENT {
+                    let onion = onion_frames.iter().find_map(|(frame, alpha)| {
+                        let color = frame.get_pixel(col, row);
+                        (color != PixelColor::TRANSPARENT).then_some((color, *alpha))
+                    });
+                    if let Some((color, alpha)) = onion {
+                        context.set_global_alpha(alpha);
+                        context.set_fill_style_str(color.to_rgb_str());
+                        context.begin_path();
+                        context
+                            .round_rect_with_f64(
+                                x,
+                                y,
+                                scaled_pixel_size,
+                                scaled_pixel_size,
+                                scaled_border_radius,
+                            )
+                            .unwrap();
+                        context.fill();
+                        context.set_global_alpha(1.0);
+                    }
+                }
                 // Draw border
             }
         }
+        self.draw_selection(context, dash_offset);
+    }
+
+    /// Outline of the cells hovered by the other users,tagged with their name and tool
+    pub fn draw_cursors(&self, context: &CanvasRenderingContext2d, cursors: impl Iterator<Item = RemoteCursor>) {
+        let (width, height) = self.grid_dimension();
+        let cell_size = (PIXEL_SIZE + GAP) * self.zoom;
+        let scaled_pixel_size = PIXEL_SIZE * self.zoom;
+        context.set_line_width(CURSOR_LINE_WIDTH);
+        context.set_font(CURSOR_TAG_FONT);
+        for cursor in cursors {
+            if cursor.cell.x >= width || cursor.cell.y >= height {
+                continue;
+            }
+            let x = self.position.x() + cursor.cell.x as f64 * cell_size;
+            let y = self.position.y() + cursor.cell.y as f64 * cell_size;
+            context.set_stroke_style_str(&cursor.color);
+            context.begin_path();
+            context
+                .round_rect_with_f64(x, y, scaled_pixel_size, scaled_pixel_size, BORDER_RADIUS * self.zoom)
+                .unwrap();
+            context.stroke();
+
+            // Tag above the cell
+            let label = format!("{} · {}", cursor.name, cursor.tool);
+            let text_width = context.measure_text(&label).map_or(0.0, |metrics| metrics.width());
+            let tag_height = 16.0;
+            let tag_y = y - tag_height - 2.0;
+            context.set_fill_style_str(&cursor.color);
+            context.fill_rect(x, tag_y, text_width + 8.0, tag_height);
+            context.set_fill_style_str("#ffffff");
+            context.fill_text(&label, x + 4.0, tag_y + 12.0).unwrap();
+        }
+        context.set_line_width(PIXEL_LINE_WIDTH);
+        context.set_stroke_style_str(PIXEL_STROKE_COLOR);
+    }
+
+    /// Marching ants around the selected cells
+    fn draw_selection(&self, context: &CanvasRenderingContext2d, dash_offset: f64) {
+        let Some(selection) = &self.selection else {
+            return;
+        };
+        let scaled_gap = GAP * self.zoom;
+        let cell_size = (PIXEL_SIZE + GAP) * self.zoom;
+        let rect = &selection.rect;
+        let x = self.position.x() + rect.left as f64 * cell_size - scaled_gap / 2.0;
+        let y = self.position.y() + rect.top as f64 * cell_size - scaled_gap / 2.0;
+        let width = rect.width as f64 * cell_size;
+        let height = rect.height as f64 * cell_size;
+
+        let dash = js_sys::Array::of2(&SELECTION_DASH.into(), &SELECTION_DASH.into());
+        context.set_line_width(SELECTION_LINE_WIDTH);
+        context.set_line_dash(&dash).unwrap();
+        context.set_line_dash_offset(0.0);
+        context.set_stroke_style_str("#ffffff");
+        context.stroke_rect(x, y, width, height);
+        context.set_line_dash_offset(dash_offset);
+        context.set_stroke_style_str("#000000");
+        context.stroke_rect(x, y, width, height);
+
+        context.set_line_dash(&js_sys::Array::new()).unwrap();
+        context.set_line_dash_offset(0.0);
+        context.set_line_width(PIXEL_LINE_WIDTH);
+        context.set_stroke_style_str(PIXEL_STROKE_COLOR);
     }
 
     /// Helper method to draw rounded rectangle
@@ -285,3 +935,9 @@ impl PixelCanvas {
         }
     }
 }
+
+fn erase_rect(canvas: &mut DrawingPixelCanvas, rect: &GridRect) {
+    for pos in rect.cells() {
+        canvas.draw_pixel_ignore(pos.x, pos.y, PixelColor::ERASE);
+    }
+}
@@ -0,0 +1,93 @@
+use std::collections::BTreeMap;
+
+use frontend::protocol::Presence;
+
+use crate::types::pixel_canvas::GridIndex;
+
+/// Cursor of another client of the session
+#[derive(Clone, Debug, PartialEq)]
+pub struct RemoteCursor {
+    pub cell: GridIndex,
+    /// Name of the tool it holds
+    pub tool: String,
+    pub name: String,
+    /// Css color of the cursor and its tag
+    pub color: String,
+}
+
+/// Another client of the session
+#[derive(Clone, Debug, PartialEq)]
+pub struct Participant {
+    pub name: String,
+    pub presence: Presence,
+}
+
+/// The clients connected to the session,keyed by the client id given by the server
+#[derive(Clone, Debug, Default)]
+pub struct Participants {
+    /// Id of this client,None until the session is joined
+    me: Option<u64>,
+    others: BTreeMap<u64, Participant>,
+}
+
+impl Participants {
+    pub fn me(&self) -> Option<u64> {
+        self.me
+    }
+    pub fn set_me(&mut self, client: u64) {
+        self.me = Some(client);
+    }
+    /// A client joined or changed its name
+    pub fn join(&mut self, client: u64, name: String) {
+        self.participant_mut(client).name = name;
+    }
+    pub fn update_presence(&mut self, client: u64, presence: Presence) {
+        self.participant_mut(client).presence = presence;
+    }
+    fn participant_mut(&mut self, client: u64) -> &mut Participant {
+        self.others.entry(client).or_insert_with(|| Participant {
+            name: participant_name(client),
+            presence: Presence::default(),
+        })
+    }
+    /// The client left,its cursor is no longer shown
+    pub fn remove(&mut self, client: u64) {
+        self.others.remove(&client);
+    }
+    /// The connection was lost,everyone is sent again on the next join
+    pub fn clear(&mut self) {
+        self.me = None;
+        self.others.clear();
+    }
+    /// The other clients,oldest first
+    pub fn others(&self) -> impl Iterator<Item = (u64, &Participant)> {
+        self.others.iter().map(|(client, participant)| (*client, participant))
+    }
+    /// Cursors of the clients hovering the grid
+    pub fn cursors(&self) -> impl Iterator<Item = RemoteCursor> + '_ {
+        self.others().filter_map(|(client, participant)| {
+            let (x, y) = participant.presence.cell?;
+            Some(RemoteCursor {
+                cell: GridIndex {
+                    x: x as usize,
+                    y: y as usize,
+                },
+                tool: participant.presence.tool.clone(),
+                name: participant.name.clone(),
+                color: cursor_color(client),
+            })
+        })
+    }
+}
+
+/// Name of a client that hasn't sent its display name yet
+pub fn participant_name(client: u64) -> String {
+    format!("Guest {client}")
+}
+
+/// Color of a client's cursor,consecutive ids get distant hues
+pub fn cursor_color(client: u64) -> String {
+    // Golden angle,so the hues never repeat and stay apart
+    let hue = (client as f64 * 137.508) % 360.0;
+    format!("hsl({hue:.0}, 70%, 45%)")
+}
@@ -0,0 +1,198 @@
+//! Images brought onto the grid: fitted to the grid and reduced to the colors of a palette.
+use std::io::Cursor;
+
+use frontend::prelude::PixelColor;
+use image::{DynamicImage, ImageReader, RgbaImage, imageops, imageops::FilterType};
+
+use crate::types::selection::PixelRegion;
+
+/// Image pixels less opaque than this are left empty
+const ALPHA_THRESHOLD: u8 = 128;
+/// Longest side of an imported image,larger ones are refused before they are decoded
+const MAX_IMPORT_SIDE: u32 = 4096;
+
+/// Scale an image to fit the grid,keeping its aspect ratio,centered on transparent cells
+pub fn fit(image: &DynamicImage, (width, height): (usize, usize)) -> RgbaImage {
+    let (width, height) = (width as u32, height as u32);
+    // Averaging when shrinking,but keep the hard edges of small pixel art when it is scaled up
+    let filter = if image.width() > width || image.height() > height {
+        FilterType::Triangle
+    } else {
+        FilterType::Nearest
+    };
+    let resized = image.resize(width, height, filter).to_rgba8();
+    let mut fitted = RgbaImage::new(width, height);
+    let x = (width - resized.width()) / 2;
+    let y = (height - resized.height()) / 2;
+    imageops::replace(&mut fitted, &resized, x as i64, y as i64);
+    fitted
+}
+
+fn nearest(palette: &[[u8; 4]], [r, g, b]: [f32; 3]) -> [u8; 4] {
+    *palette
+        .iter()
+        .min_by(|a, b| distance(a, [r, g, b]).total_cmp(&distance(b, [r, g, b])))
+        .expect("the palette isn't empty")
+}
+
+fn distance(color: &[u8; 4], [r, g, b]: [f32; 3]) -> f32 {
+    let dr = color[0] as f32 - r;
+    let dg = color[1] as f32 - g;
+    let db = color[2] as f32 - b;
+    dr * dr + dg * dg + db * db
+}
+
+/// Replace every pixel by the closest palette color.
+/// With `dither` the rounding error is spread on the next pixels (Floyd–Steinberg).
+pub fn quantize(image: &RgbaImage, palette: &[PixelColor], dither: bool) -> Result<PixelRegion, String> {
+    let palette: Vec<[u8; 4]> = palette
+        .iter()
+        .filter(|color| **color != PixelColor::TRANSPARENT && **color != PixelColor::ERASE)
+        .map(|color| color.to_rgba())
+        .collect();
+    if palette.is_empty() {
+        return Err("the palette has no color".to_string());
+    }
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    // Error carried to each pixel by its already quantized neighbours
+    let mut errors = vec![[0f32; 3]; width * height];
+    let mut pixels = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            let [r, g, b, a] = image.get_pixel(x as u32, y as u32).0;
+            if a < ALPHA_THRESHOLD {
+                pixels.push(PixelColor::TRANSPARENT);
+                continue;
+            }
+            let error = errors[y * width + x];
+            let wanted = [
+                (r as f32 + error[0]).clamp(0.0, 255.0),
+                (g as f32 + error[1]).clamp(0.0, 255.0),
+                (b as f32 + error[2]).clamp(0.0, 255.0),
+            ];
+            let color = nearest(&palette, wanted);
+            pixels.push(PixelColor::new(color[0], color[1], color[2], color[3]));
+            if !dither {
+                continue;
+            }
+            let error = [
+                wanted[0] - color[0] as f32,
+                wanted[1] - color[1] as f32,
+                wanted[2] - color[2] as f32,
+            ];
+            let mut spread = |dx: isize, dy: usize, weight: f32| {
+                let Some(x) = x.checked_add_signed(dx).filter(|x| *x < width) else {
+                    return;
+                };
+                if y + dy < height {
+                    let target = &mut errors[(y + dy) * width + x];
+                    for (target, error) in target.iter_mut().zip(error) {
+                        *target += error * weight;
+                    }
+                }
+            };
+            spread(1, 0, 7.0 / 16.0);
+            spread(-1, 1, 3.0 / 16.0);
+            spread(0, 1, 5.0 / 16.0);
+            spread(1, 1, 1.0 / 16.0);
+        }
+    }
+    Ok(PixelRegion {
+        width,
+        height,
+        pixels,
+    })
+}
+
+/// Decode a png or jpeg,fit it to the grid and reduce it to the palette.
+/// Images with a side longer than [`MAX_IMPORT_SIDE`] are rejected before they are decoded.
+pub fn import_image(
+    bytes: &[u8],
+    grid_dimension: (usize, usize),
+    palette: &[PixelColor],
+    dither: bool,
+) -> Result<PixelRegion, String> {
+    let reader = || {
+        ImageReader::new(Cursor::new(bytes))
+            .with_guessed_format()
+            .map_err(|e| format!("Failed to read image: {e}"))
+    };
+    let (width, height) = reader()?
+        .into_dimensions()
+        .map_err(|e| format!("Failed to decode image: {e}"))?;
+    if width.max(height) > MAX_IMPORT_SIDE {
+        return Err(format!(
+            "The image is {width}x{height}, images can't be larger than {MAX_IMPORT_SIDE}x{MAX_IMPORT_SIDE}"
+        ));
+    }
+    let image = reader()?
+        .decode()
+        .map_err(|e| format!("Failed to decode image: {e}"))?;
+    quantize(&fit(&image, grid_dimension), palette, dither)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    fn black_and_white() -> Vec<PixelColor> {
+        vec![
+            PixelColor::new(0, 0, 0, 255),
+            PixelColor::new(255, 255, 255, 255),
+        ]
+    }
+
+    #[test]
+    fn picks_the_closest_color_without_dither() {
+        let mut image = RgbaImage::new(3, 1);
+        image.put_pixel(0, 0, Rgba([10, 10, 10, 255]));
+        image.put_pixel(1, 0, Rgba([250, 240, 230, 255]));
+        image.put_pixel(2, 0, Rgba([250, 240, 230, 100]));
+        let region = quantize(&image, &black_and_white(), false).unwrap();
+
+        assert_eq!((region.width, region.height), (3, 1));
+        assert_eq!(
+            region.pixels,
+            vec![
+                PixelColor::new(0, 0, 0, 255),
+                PixelColor::new(255, 255, 255, 255),
+                PixelColor::TRANSPARENT,
+            ]
+        );
+    }
+
+    #[test]
+    fn spreads_the_error_with_dither() {
+        let image = RgbaImage::from_pixel(4, 4, Rgba([128, 128, 128, 255]));
+        let white = PixelColor::new(255, 255, 255, 255);
+        let black = PixelColor::new(0, 0, 0, 255);
+
+        let flat = quantize(&image, &black_and_white(), false).unwrap();
+        assert!(flat.pixels.iter().all(|color| *color == white));
+
+        let dithered = quantize(&image, &black_and_white(), true).unwrap();
+        let whites = dithered
+            .pixels
+            .iter()
+            .filter(|color| **color == white)
+            .count();
+        let blacks = dithered
+            .pixels
+            .iter()
+            .filter(|color| **color == black)
+            .count();
+        assert_eq!(whites + blacks, 16);
+        assert!(
+            (6..=10).contains(&whites),
+            "{whites} white pixels out of 16"
+        );
+    }
+
+    #[test]
+    fn rejects_palettes_without_color() {
+        let image = RgbaImage::from_pixel(1, 1, Rgba([0, 0, 0, 255]));
+        assert!(quantize(&image, &[], false).is_err());
+        assert!(quantize(&image, &[PixelColor::TRANSPARENT, PixelColor::ERASE], true).is_err());
+    }
+}
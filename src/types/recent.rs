@@ -0,0 +1,90 @@
+use frontend::protocol::clean_name;
+use leptos::logging::{error, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::services::storage::{self, RECENT_SESSIONS_KEY};
+
+/// Sessions remembered by the browser,older ones are forgotten
+pub const MAX_RECENT_SESSIONS: usize = 50;
+/// Name of a session until it is renamed or published
+pub const DEFAULT_SESSION_NAME: &str = "Untitled";
+
+/// A session opened in this browser
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RecentSession {
+    pub id: String,
+    pub name: String,
+    pub width: usize,
+    pub height: usize,
+    /// Last time it was opened,in milliseconds since the unix epoch
+    pub visited_at: u64,
+    /// Key of the page link,so the session opens again with the same role
+    pub key: Option<String>,
+}
+
+/// Sessions opened in this browser,the last visited first,kept in localStorage
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RecentSessions {
+    sessions: Vec<RecentSession>,
+}
+
+impl RecentSessions {
+    /// The sessions stored by the browser,none if they can't be read
+    pub fn load() -> Self {
+        let sessions = storage::get(RECENT_SESSIONS_KEY)
+            .and_then(|json| {
+                serde_json::from_str(&json)
+                    .inspect_err(|e| warn!("Forgetting corrupted recent sessions: {e}"))
+                    .ok()
+            })
+            .unwrap_or_default();
+        Self { sessions }
+    }
+    fn save(&self) {
+        let json = serde_json::to_string(&self.sessions).expect("recent sessions are serializable");
+        if let Err(err) = storage::set(RECENT_SESSIONS_KEY, &json) {
+            error!("{err}");
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &RecentSession> {
+        self.sessions.iter()
+    }
+    pub fn get(&self, id: &str) -> Option<&RecentSession> {
+        self.sessions.iter().find(|session| session.id == id)
+    }
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+
+    /// Move a session to the front,keeping the name it was given.
+    /// The name of `visited` is only used for a session seen for the first time,
+    /// a link without key keeps the key it was opened with before.
+    pub fn visit(&mut self, mut visited: RecentSession) {
+        if let Some(index) = self.sessions.iter().position(|session| session.id == visited.id) {
+            let previous = self.sessions.remove(index);
+            visited.name = previous.name;
+            visited.key = visited.key.or(previous.key);
+        }
+        self.sessions.insert(0, visited);
+        self.sessions.truncate(MAX_RECENT_SESSIONS);
+        self.save();
+    }
+    /// Give a session another name,cut to the longest display name.
+    /// False if nothing is left of the name.
+    pub fn rename(&mut self, id: &str, name: &str) -> bool {
+        let Some(name) = clean_name(name) else {
+            return false;
+        };
+        if let Some(session) = self.sessions.iter_mut().find(|session| session.id == id) {
+            session.name = name;
+            self.save();
+        }
+        true
+    }
+    /// Forget a session,it stays on the server
+    pub fn remove(&mut self, id: &str) {
+        self.sessions.retain(|session| session.id != id);
+        self.save();
+    }
+}
@@ -0,0 +1,145 @@
+use std::io::Cursor;
+
+use frontend::prelude::{DrawingPixelCanvas, PixelColor};
+
+use crate::types::pixel_canvas::GridIndex;
+
+/// Rectangle of grid cells,left/top included and right/bottom excluded
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GridRect {
+    pub left: usize,
+    pub top: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl GridRect {
+    /// Rectangle covering both corner cells
+    pub fn from_corners(a: &GridIndex, b: &GridIndex) -> Self {
+        let (left, right) = (a.x.min(b.x), a.x.max(b.x));
+        let (top, bottom) = (a.y.min(b.y), a.y.max(b.y));
+        Self {
+            left,
+            top,
+            width: right - left + 1,
+            height: bottom - top + 1,
+        }
+    }
+    pub fn contains(&self, pos: &GridIndex) -> bool {
+        (self.left..self.left + self.width).contains(&pos.x)
+            && (self.top..self.top + self.height).contains(&pos.y)
+    }
+    /// Clamp the rectangle inside a grid of the given dimension
+    pub fn clamp(&self, (width, height): (usize, usize)) -> Self {
+        let left = self.left.min(width.saturating_sub(1));
+        let top = self.top.min(height.saturating_sub(1));
+        Self {
+            left,
+            top,
+            width: self.width.min(width - left),
+            height: self.height.min(height - top),
+        }
+    }
+    /// Move the rectangle,it never goes past the top-left edge of the grid
+    pub fn shift(&mut self, dx: isize, dy: isize) {
+        self.left = self.left.saturating_add_signed(dx);
+        self.top = self.top.saturating_add_signed(dy);
+    }
+    /// Every cell of the rectangle,row by row
+    pub fn cells(&self) -> impl Iterator<Item = GridIndex> + '_ {
+        (self.top..self.top + self.height)
+            .flat_map(move |y| (self.left..self.left + self.width).map(move |x| GridIndex { x, y }))
+    }
+}
+
+/// Pixels copied out of a canvas
+#[derive(Clone, Debug, PartialEq)]
+pub struct PixelRegion {
+    pub width: usize,
+    pub height: usize,
+    /// Row-major pixels
+    pub pixels: Vec<PixelColor>,
+}
+
+impl PixelRegion {
+    pub fn copy_from(canvas: &DrawingPixelCanvas, rect: &GridRect) -> Self {
+        Self {
+            width: rect.width,
+            height: rect.height,
+            pixels: rect
+                .cells()
+                .map(|pos| canvas.get_pixel(pos.x, pos.y))
+                .collect(),
+        }
+    }
+    pub fn get_pixel(&self, x: usize, y: usize) -> PixelColor {
+        self.pixels[y * self.width + x]
+    }
+    /// Draw the region with its top-left corner at `at`,transparent pixels are skipped
+    pub fn paste_on(&self, canvas: &mut DrawingPixelCanvas, at: &GridIndex) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let color = self.get_pixel(x, y);
+                if color != PixelColor::TRANSPARENT {
+                    canvas.draw_pixel_ignore(at.x + x, at.y + y, color);
+                }
+            }
+        }
+    }
+    pub fn to_png_bytes(&self) -> Result<Vec<u8>, String> {
+        let image = image::RgbaImage::from_fn(self.width as u32, self.height as u32, |x, y| {
+            image::Rgba(self.get_pixel(x as usize, y as usize).to_rgba())
+        });
+        let mut bytes = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut bytes), image::ImageFormat::Png)
+            .map_err(|e| format!("Failed to encode png: {e}"))?;
+        Ok(bytes)
+    }
+    /// Decode any image format supported by the image crate.
+    /// Images with more than `max_width`x`max_height` pixels are rejected before they are decoded.
+    pub fn from_image_bytes(bytes: &[u8], max_width: usize, max_height: usize) -> Result<Self, String> {
+        let reader = || {
+            image::ImageReader::new(Cursor::new(bytes))
+                .with_guessed_format()
+                .map_err(|e| format!("Failed to read image: {e}"))
+        };
+        let (width, height) = reader()?
+            .into_dimensions()
+            .map_err(|e| format!("Failed to decode image: {e}"))?;
+        if width as usize > max_width || height as usize > max_height {
+            return Err(format!(
+                "The image is {width}x{height}, larger than the {max_width}x{max_height} canvas"
+            ));
+        }
+        let image = reader()?
+            .decode()
+            .map_err(|e| format!("Failed to decode image: {e}"))?
+            .to_rgba8();
+        Ok(Self {
+            width: image.width() as usize,
+            height: image.height() as usize,
+            pixels: image
+                .pixels()
+                .map(|&image::Rgba([r, g, b, a])| {
+                    if a == 0 {
+                        PixelColor::TRANSPARENT
+                    } else {
+                        PixelColor::new(r, g, b, a)
+                    }
+                })
+                .collect(),
+        })
+    }
+}
+
+/// Selected rectangle of the canvas
+#[derive(Clone, Debug)]
+pub struct Selection {
+    pub rect: GridRect,
+    /// Pixels lifted out of the canvas while they are moved or pasted,shown at `rect`
+    pub floating: Option<PixelRegion>,
+    /// Where the floating pixels were lifted from,erased when they are dropped.
+    /// None for copies and pasted regions.
+    pub source: Option<GridRect>,
+}
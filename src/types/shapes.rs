@@ -0,0 +1,124 @@
+use crate::types::pixel_canvas::GridIndex;
+
+/// Shapes drawn by dragging from one corner to the other
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shape {
+    Line,
+    Rectangle,
+    FilledRectangle,
+    Ellipse,
+}
+
+impl Shape {
+    /// Pixels of the shape between the drag start and end
+    pub fn pixels(&self, start: &GridIndex, end: &GridIndex) -> Vec<GridIndex> {
+        let (x0, y0) = (start.x as i64, start.y as i64);
+        let (x1, y1) = (end.x as i64, end.y as i64);
+        let points = match self {
+            Shape::Line => line(x0, y0, x1, y1),
+            Shape::Rectangle => rectangle(x0, y0, x1, y1, false),
+            Shape::FilledRectangle => rectangle(x0, y0, x1, y1, true),
+            Shape::Ellipse => ellipse(x0, y0, x1, y1),
+        };
+        points
+            .into_iter()
+            .filter(|&(x, y)| x >= 0 && y >= 0)
+            .map(|(x, y)| GridIndex {
+                x: x as usize,
+                y: y as usize,
+            })
+            .collect()
+    }
+}
+
+/// Bresenham's line algorithm
+fn line(mut x0: i64, mut y0: i64, x1: i64, y1: i64) -> Vec<(i64, i64)> {
+    let dx = (x1 - x0).abs();
+    let dy = -(y1 - y0).abs();
+    let sx = if x0 < x1 { 1 } else { -1 };
+    let sy = if y0 < y1 { 1 } else { -1 };
+    let mut err = dx + dy;
+    let mut points = Vec::new();
+    loop {
+        points.push((x0, y0));
+        if x0 == x1 && y0 == y1 {
+            break;
+        }
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x0 += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            y0 += sy;
+        }
+    }
+    points
+}
+
+fn rectangle(x0: i64, y0: i64, x1: i64, y1: i64, filled: bool) -> Vec<(i64, i64)> {
+    let (left, right) = (x0.min(x1), x0.max(x1));
+    let (top, bottom) = (y0.min(y1), y0.max(y1));
+    let mut points = Vec::new();
+    for y in top..=bottom {
+        if filled || y == top || y == bottom {
+            points.extend((left..=right).map(|x| (x, y)));
+        } else {
+            points.push((left, y));
+            points.push((right, y));
+        }
+    }
+    points
+}
+
+/// Ellipse fitting in the rectangle between the two corners.
+/// Alois Zingl's rasterizing algorithm,works for even and odd sizes.
+fn ellipse(mut x0: i64, mut y0: i64, mut x1: i64, mut y1: i64) -> Vec<(i64, i64)> {
+    let mut a = (x1 - x0).abs();
+    let b = (y1 - y0).abs();
+    let mut b1 = b & 1;
+    let mut dx = 4.0 * (1.0 - a as f64) * (b * b) as f64;
+    let mut dy = 4.0 * (b1 + 1) as f64 * (a * a) as f64;
+    let mut err = dx + dy + (b1 * a * a) as f64;
+
+    if x0 > x1 {
+        x0 = x1;
+        x1 += a;
+    }
+    if y0 > y1 {
+        y0 = y1;
+    }
+    y0 += (b + 1) / 2;
+    y1 = y0 - b1;
+    a *= 8 * a;
+    b1 = 8 * b * b;
+
+    let mut points = Vec::new();
+    loop {
+        points.extend([(x1, y0), (x0, y0), (x0, y1), (x1, y1)]);
+        let e2 = 2.0 * err;
+        if e2 <= dy {
+            y0 += 1;
+            y1 -= 1;
+            dy += a as f64;
+            err += dy;
+        }
+        if e2 >= dx || 2.0 * err > dy {
+            x0 += 1;
+            x1 -= 1;
+            dx += b1 as f64;
+            err += dx;
+        }
+        if x0 > x1 {
+            break;
+        }
+    }
+    // Flat ellipses stop too early,finish their tips
+    while y0 - y1 <= b {
+        points.extend([(x0 - 1, y0), (x1 + 1, y0), (x0 - 1, y1), (x1 + 1, y1)]);
+        y0 += 1;
+        y1 -= 1;
+    }
+    points
+}
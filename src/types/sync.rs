@@ -0,0 +1,37 @@
+use std::collections::VecDeque;
+
+use frontend::protocol::CanvasDelta;
+
+/// Outgoing deltas of this client,kept until the server acknowledges them
+#[derive(Debug, Default)]
+pub struct SyncState {
+    /// Sequence number of the last delta sent
+    last_seq: u64,
+    /// Deltas sent but not acknowledged yet
+    pending: VecDeque<CanvasDelta>,
+}
+
+impl SyncState {
+    /// Sequence number the next delta should carry
+    pub fn next_seq(&self) -> u64 {
+        self.last_seq + 1
+    }
+    pub fn push(&mut self, delta: CanvasDelta) {
+        self.last_seq = delta.seq;
+        self.pending.push_back(delta);
+    }
+    /// The server merged every delta up to `seq`
+    pub fn ack(&mut self, seq: u64) {
+        while self.pending.front().is_some_and(|delta| delta.seq <= seq) {
+            self.pending.pop_front();
+        }
+    }
+    /// Forget the deltas not acknowledged yet,they were drawn on a board that changed size
+    pub fn drop_pending(&mut self) {
+        self.pending.clear();
+    }
+    /// Deltas the server hasn't acknowledged,oldest first
+    pub fn pending(&self) -> impl Iterator<Item = &CanvasDelta> {
+        self.pending.iter()
+    }
+}